@@ -0,0 +1,550 @@
+//! The window manager as seen by winj and wint.
+//!
+//! Everything that queries or changes window state goes through [`Backend`], so the
+//! jump and tiling logic can run against [`MockBackend`] instead of a live compositor.
+use crate::hyprland::{HyprOption, WorkspaceRef};
+use crate::{Client, Hyprland, Monitor, Window, WintError, Workspace};
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::thread;
+use std::time::Duration;
+
+/// A request to change the window state
+#[derive(Debug, Clone, PartialEq)]
+pub enum Dispatch {
+    Focus(Window),
+    Move(Window, i32, i32),
+    Resize(Window, i32, i32),
+    Raise(Window),
+    Workspace(String),
+    ToggleFloating(Window),
+    SetFloating(Window),
+    SetTiled(Window),
+    /// Send the window to the workspace and follow it there
+    MoveToWorkspace(Window, String),
+    /// Send the window to the workspace and stay where we are
+    MoveToWorkspaceSilent(Window, String),
+    /// Show or hide the special workspace with this name, `""` for the unnamed one
+    ToggleSpecialWorkspace(String),
+    Close(Window),
+    /// Toggle fullscreen on the active window
+    Fullscreen,
+    TogglePin(Window),
+}
+
+/// Formats as the argument of Hyprland's `dispatch` request
+impl fmt::Display for Dispatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Dispatch::Focus(w) => write!(f, "focuswindow address:0x{:x}", w),
+            Dispatch::Move(w, x, y) => {
+                write!(f, "movewindowpixel exact {} {},address:0x{:x}", x, y, w)
+            }
+            Dispatch::Resize(w, width, height) => write!(
+                f,
+                "resizewindowpixel exact {} {},address:0x{:x}",
+                width, height, w
+            ),
+            Dispatch::Raise(w) => write!(f, "alterzorder top,address:0x{:x}", w),
+            Dispatch::Workspace(ref ws) => write!(f, "workspace {}", ws),
+            Dispatch::ToggleFloating(w) => write!(f, "togglefloating address:0x{:x}", w),
+            Dispatch::SetFloating(w) => write!(f, "setfloating address:0x{:x}", w),
+            Dispatch::SetTiled(w) => write!(f, "settiled address:0x{:x}", w),
+            Dispatch::MoveToWorkspace(w, ref ws) => {
+                write!(f, "movetoworkspace {},address:0x{:x}", ws, w)
+            }
+            Dispatch::MoveToWorkspaceSilent(w, ref ws) => {
+                write!(f, "movetoworkspacesilent {},address:0x{:x}", ws, w)
+            }
+            Dispatch::ToggleSpecialWorkspace(ref name) => {
+                write!(f, "togglespecialworkspace {}", name)
+            }
+            Dispatch::Close(w) => write!(f, "closewindow address:0x{:x}", w),
+            Dispatch::Fullscreen => write!(f, "fullscreen"),
+            Dispatch::TogglePin(w) => write!(f, "pin address:0x{:x}", w),
+        }
+    }
+}
+
+pub trait Backend {
+    fn clients(&self) -> Result<Vec<Client>, WintError>;
+    fn monitors(&self) -> Result<Vec<Monitor>, WintError>;
+    fn active_workspace(&self) -> Result<Workspace, WintError>;
+    fn active_window(&self) -> Result<Option<Client>, WintError>;
+    fn option(&self, name: &str) -> Result<HyprOption, WintError>;
+    fn execute(&self, cmd: &Dispatch) -> Result<(), WintError>;
+
+    /// Run the requests as one transaction, where the window manager supports it
+    fn execute_batch(&self, cmds: &[Dispatch]) -> Result<(), WintError> {
+        cmds.iter().try_for_each(|cmd| self.execute(cmd))
+    }
+
+    fn client(&self, win: Window) -> Result<Option<Client>, WintError> {
+        Ok(self.clients()?.into_iter().find(|c| c.address == win))
+    }
+
+    fn focus(&self, win: Window) -> Result<(), WintError> {
+        self.execute(&Dispatch::Focus(win))
+    }
+    fn move_to(&self, win: Window, x: i32, y: i32) -> Result<(), WintError> {
+        self.execute(&Dispatch::Move(win, x, y))
+    }
+    fn resize(&self, win: Window, width: i32, height: i32) -> Result<(), WintError> {
+        self.execute(&Dispatch::Resize(win, width, height))
+    }
+    fn raise(&self, win: Window) -> Result<(), WintError> {
+        self.execute(&Dispatch::Raise(win))
+    }
+    /// Go to the workspace given by number or by name, see [`workspace_selector`];
+    /// special workspaces (`special` or `special:NAME`) are shown or hidden instead
+    fn switch_workspace(&self, workspace: &str) -> Result<(), WintError> {
+        match special_name(workspace) {
+            Some(name) => self.execute(&Dispatch::ToggleSpecialWorkspace(name.to_string())),
+            None => self.execute(&Dispatch::Workspace(workspace_selector(workspace))),
+        }
+    }
+    fn move_to_workspace(
+        &self,
+        win: Window,
+        workspace: &str,
+        silent: bool,
+    ) -> Result<(), WintError> {
+        if silent {
+            self.execute(&Dispatch::MoveToWorkspaceSilent(win, workspace.to_string()))
+        } else {
+            self.execute(&Dispatch::MoveToWorkspace(win, workspace.to_string()))
+        }
+    }
+}
+
+impl Backend for Hyprland {
+    fn clients(&self) -> Result<Vec<Client>, WintError> {
+        Hyprland::clients(self)
+    }
+    fn monitors(&self) -> Result<Vec<Monitor>, WintError> {
+        Hyprland::monitors(self)
+    }
+    fn active_workspace(&self) -> Result<Workspace, WintError> {
+        Hyprland::active_workspace(self)
+    }
+    fn active_window(&self) -> Result<Option<Client>, WintError> {
+        Hyprland::active_window(self)
+    }
+    fn option(&self, name: &str) -> Result<HyprOption, WintError> {
+        Hyprland::option(self, name)
+    }
+    fn execute(&self, cmd: &Dispatch) -> Result<(), WintError> {
+        self.dispatch(&cmd.to_string())
+    }
+    fn execute_batch(&self, cmds: &[Dispatch]) -> Result<(), WintError> {
+        self.dispatch_batch(&cmds.iter().map(|c| c.to_string()).collect::<Vec<String>>())
+    }
+}
+
+/// The `<delay>` and `<attempts>` settings: how long to wait for the window manager
+/// after a request, and how many times to send it before giving up
+#[derive(Debug, Clone, Copy)]
+pub struct Retry {
+    pub delay: Duration,
+    pub attempts: u32,
+}
+
+impl Default for Retry {
+    fn default() -> Retry {
+        Retry {
+            delay: Duration::from_millis(100),
+            attempts: 5,
+        }
+    }
+}
+
+impl Retry {
+    /// Run `action` until `done` reports that the window manager has complied
+    pub fn run<A, D>(&self, what: &str, mut action: A, mut done: D) -> Result<(), WintError>
+    where
+        A: FnMut() -> Result<(), WintError>,
+        D: FnMut() -> Result<bool, WintError>,
+    {
+        for attempt in 1..=self.attempts.max(1) {
+            action()?;
+            thread::sleep(self.delay);
+            if done()? {
+                return Ok(());
+            }
+            println!(
+                "-- {}: not done after attempt {} of {}",
+                what, attempt, self.attempts
+            );
+        }
+        Err(WintError::NotApplied(what.to_string()))
+    }
+
+    /// Send `action` once and wait until `done` reports that it took effect: a toggle
+    /// sent again while the window manager lags behind would undo the first one
+    pub fn toggle<A, D>(&self, what: &str, action: A, mut done: D) -> Result<(), WintError>
+    where
+        A: FnOnce() -> Result<(), WintError>,
+        D: FnMut() -> Result<bool, WintError>,
+    {
+        action()?;
+        for attempt in 1..=self.attempts.max(1) {
+            thread::sleep(self.delay);
+            if done()? {
+                return Ok(());
+            }
+            println!(
+                "-- {}: not done after check {} of {}",
+                what, attempt, self.attempts
+            );
+        }
+        Err(WintError::NotApplied(what.to_string()))
+    }
+
+    pub fn focus(&self, backend: &dyn Backend, win: Window) -> Result<(), WintError> {
+        self.run(
+            &format!("focus window 0x{:x}", win),
+            || backend.focus(win),
+            || Ok(backend.active_window()?.map(|c| c.address) == Some(win)),
+        )
+    }
+
+    pub fn move_to(
+        &self,
+        backend: &dyn Backend,
+        win: Window,
+        x: i32,
+        y: i32,
+    ) -> Result<(), WintError> {
+        self.run(
+            &format!("move window 0x{:x} to {},{}", win, x, y),
+            || backend.move_to(win, x, y),
+            || Ok(backend.client(win)?.is_some_and(|c| close_to(c.at, [x, y]))),
+        )
+    }
+
+    pub fn resize(
+        &self,
+        backend: &dyn Backend,
+        win: Window,
+        width: i32,
+        height: i32,
+    ) -> Result<(), WintError> {
+        self.run(
+            &format!("resize window 0x{:x} to {}x{}", win, width, height),
+            || backend.resize(win, width, height),
+            || {
+                Ok(backend
+                    .client(win)?
+                    .is_some_and(|c| close_to(c.size, [width, height])))
+            },
+        )
+    }
+
+    /// Send the window to the workspace, given by number or by name, see [`workspace_selector`]
+    pub fn move_to_workspace(
+        &self,
+        backend: &dyn Backend,
+        win: Window,
+        workspace: &str,
+        silent: bool,
+    ) -> Result<(), WintError> {
+        let selector = workspace_selector(workspace);
+        let workspace = selector.as_str();
+        // Hyprland reports `name:NAME` workspaces by their bare name
+        let name = workspace.strip_prefix("name:").unwrap_or(workspace);
+        self.run(
+            &format!("move window 0x{:x} to workspace {}", win, workspace),
+            || backend.move_to_workspace(win, workspace, silent),
+            || {
+                Ok(backend.client(win)?.is_some_and(|c| {
+                    c.workspace.name == name
+                        || c.workspace.id.to_string() == workspace
+                        || special_name(workspace)
+                            .is_some_and(|n| special_name(&c.workspace.name) == Some(n))
+                }))
+            },
+        )
+    }
+}
+
+/// Hyprland's selector for a workspace typed by the user: digits are the id, `special`,
+/// `special:NAME` and `name:NAME` are kept, and anything else is a name. Relative
+/// selectors such as `r+1` are names too, since a move sent again would go further.
+pub fn workspace_selector(typed: &str) -> String {
+    if typed.chars().all(|c| c.is_ascii_digit()) {
+        if let Ok(id) = typed.parse::<u32>() {
+            return id.to_string();
+        }
+    }
+    if special_name(typed).is_some() || typed.starts_with("name:") {
+        return typed.to_string();
+    }
+    format!("name:{}", typed)
+}
+
+/// The name of a special workspace: `special` is the unnamed one, which Hyprland
+/// calls `special:special`, and `special:NAME` a named one
+pub fn special_name(workspace: &str) -> Option<&str> {
+    match workspace {
+        "special" | "special:special" => Some(""),
+        _ => workspace.strip_prefix("special:"),
+    }
+}
+
+/// Hyprland rounds logical coordinates, so allow one pixel of difference
+pub(crate) fn close_to(actual: [i32; 2], wanted: [i32; 2]) -> bool {
+    (actual[0] - wanted[0]).abs() <= 1 && (actual[1] - wanted[1]).abs() <= 1
+}
+
+/// Serves a set of clients and monitors, records every dispatch, and applies
+/// focus, move, resize and floating to its clients unless `unresponsive` is set
+#[derive(Debug, Default)]
+pub struct MockBackend {
+    pub clients: RefCell<Vec<Client>>,
+    pub monitors: Vec<Monitor>,
+    pub workspace: Workspace,
+    pub active: Cell<Option<Window>>,
+    pub options: Vec<HyprOption>,
+    pub unresponsive: bool,
+    /// How many queries of the clients or the active window are answered
+    /// before a dispatch takes effect, like a busy compositor
+    pub lag: usize,
+    dispatched: RefCell<Vec<Dispatch>>,
+    batches: RefCell<Vec<Vec<Dispatch>>>,
+    queued: RefCell<Vec<(usize, Dispatch)>>,
+}
+
+impl MockBackend {
+    pub fn new(clients: Vec<Client>, monitors: Vec<Monitor>) -> MockBackend {
+        MockBackend {
+            clients: RefCell::new(clients),
+            monitors,
+            ..Default::default()
+        }
+    }
+
+    /// Everything dispatched so far, in order, including the contents of batches
+    pub fn dispatched(&self) -> Vec<Dispatch> {
+        self.dispatched.borrow().clone()
+    }
+
+    /// The batches dispatched so far
+    pub fn batches(&self) -> Vec<Vec<Dispatch>> {
+        self.batches.borrow().clone()
+    }
+
+    /// A query was answered: apply the dispatches whose lag is over
+    fn answered(&self) {
+        let due = {
+            let mut queued = self.queued.borrow_mut();
+            queued.iter_mut().for_each(|(left, _)| *left -= 1);
+            let due = queued
+                .iter()
+                .filter(|(left, _)| *left == 0)
+                .map(|(_, cmd)| cmd.clone())
+                .collect::<Vec<Dispatch>>();
+            queued.retain(|(left, _)| *left > 0);
+            due
+        };
+        due.iter().for_each(|cmd| self.apply(cmd));
+    }
+
+    fn apply(&self, cmd: &Dispatch) {
+        let mut clients = self.clients.borrow_mut();
+        match *cmd {
+            Dispatch::Focus(w) => self.active.set(Some(w)),
+            Dispatch::Move(w, x, y) => clients
+                .iter_mut()
+                .filter(|c| c.address == w)
+                .for_each(|c| c.at = [x, y]),
+            Dispatch::Resize(w, width, height) => clients
+                .iter_mut()
+                .filter(|c| c.address == w)
+                .for_each(|c| c.size = [width, height]),
+            Dispatch::ToggleFloating(w) => clients
+                .iter_mut()
+                .filter(|c| c.address == w)
+                .for_each(|c| c.floating = !c.floating),
+            Dispatch::SetFloating(w) => clients
+                .iter_mut()
+                .filter(|c| c.address == w)
+                .for_each(|c| c.floating = true),
+            Dispatch::SetTiled(w) => clients
+                .iter_mut()
+                .filter(|c| c.address == w)
+                .for_each(|c| c.floating = false),
+            Dispatch::MoveToWorkspace(w, ref ws) | Dispatch::MoveToWorkspaceSilent(w, ref ws) => {
+                clients.iter_mut().filter(|c| c.address == w).for_each(|c| {
+                    c.workspace = WorkspaceRef {
+                        id: ws.parse().unwrap_or(c.workspace.id),
+                        name: ws.strip_prefix("name:").unwrap_or(ws).to_string(),
+                    }
+                })
+            }
+            Dispatch::Close(w) => clients.retain(|c| c.address != w),
+            Dispatch::Fullscreen => clients
+                .iter_mut()
+                .filter(|c| Some(c.address) == self.active.get())
+                .for_each(|c| c.fullscreen = if c.fullscreen == 0 { 2 } else { 0 }),
+            Dispatch::TogglePin(w) => clients
+                .iter_mut()
+                .filter(|c| c.address == w)
+                .for_each(|c| c.pinned = !c.pinned),
+            _ => (),
+        }
+    }
+}
+
+impl Backend for MockBackend {
+    fn clients(&self) -> Result<Vec<Client>, WintError> {
+        let clients = self.clients.borrow().clone();
+        self.answered();
+        Ok(clients)
+    }
+    fn monitors(&self) -> Result<Vec<Monitor>, WintError> {
+        Ok(self.monitors.clone())
+    }
+    fn active_workspace(&self) -> Result<Workspace, WintError> {
+        Ok(self.workspace.clone())
+    }
+    fn active_window(&self) -> Result<Option<Client>, WintError> {
+        let active = self.active.get().and_then(|a| {
+            self.clients
+                .borrow()
+                .iter()
+                .find(|c| c.address == a)
+                .cloned()
+        });
+        self.answered();
+        Ok(active)
+    }
+    fn option(&self, name: &str) -> Result<HyprOption, WintError> {
+        self.options
+            .iter()
+            .find(|o| o.option == name)
+            .cloned()
+            .ok_or_else(|| WintError::Hyprland(String::from("no such option")))
+    }
+    fn execute(&self, cmd: &Dispatch) -> Result<(), WintError> {
+        self.dispatched.borrow_mut().push(cmd.clone());
+        if !self.unresponsive {
+            if self.lag > 0 {
+                self.queued.borrow_mut().push((self.lag, cmd.clone()));
+            } else {
+                self.apply(cmd);
+            }
+        }
+        Ok(())
+    }
+    fn execute_batch(&self, cmds: &[Dispatch]) -> Result<(), WintError> {
+        self.batches.borrow_mut().push(cmds.to_vec());
+        cmds.iter().try_for_each(|cmd| self.execute(cmd))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{client, quick};
+    use crate::{act_on_window, go_to_window, Action};
+
+    fn mock() -> MockBackend {
+        MockBackend::new(
+            vec![client(1, "", ""), client(2, "", "")],
+            vec![Monitor::default()],
+        )
+    }
+
+    #[test]
+    fn go_to_window_focuses_then_raises() {
+        let backend = mock();
+        go_to_window(&backend, 2, &quick()).unwrap();
+        assert_eq!(
+            backend.dispatched(),
+            [Dispatch::Focus(2), Dispatch::Raise(2)]
+        );
+        assert_eq!(backend.active.get(), Some(2));
+    }
+
+    #[test]
+    fn focus_is_retried_until_it_gives_up() {
+        let backend = MockBackend {
+            unresponsive: true,
+            ..mock()
+        };
+        match go_to_window(&backend, 2, &quick()) {
+            Err(WintError::NotApplied(_)) => (),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(backend.dispatched(), vec![Dispatch::Focus(2); 3]);
+    }
+
+    #[test]
+    fn named_workspaces_are_recognized() {
+        // the window is already there, as Hyprland reports it
+        let backend = MockBackend {
+            unresponsive: true,
+            ..mock()
+        };
+        let set = |ws: &str| {
+            backend.clients.borrow_mut()[0].workspace = WorkspaceRef {
+                id: -1337,
+                name: ws.to_string(),
+            }
+        };
+        set("mail");
+        quick()
+            .move_to_workspace(&backend, 1, "name:mail", true)
+            .unwrap();
+        set("special:scratch");
+        quick()
+            .move_to_workspace(&backend, 1, "special:scratch", true)
+            .unwrap();
+        set("special:special");
+        quick()
+            .move_to_workspace(&backend, 1, "special", true)
+            .unwrap();
+        assert_eq!(special_name("special:special"), Some(""));
+        assert_eq!(special_name("special:scratch"), Some("scratch"));
+        assert_eq!(special_name("scratch"), None);
+    }
+
+    #[test]
+    fn typed_workspaces() {
+        assert_eq!(workspace_selector("12"), "12");
+        assert_eq!(workspace_selector("012"), "12");
+        assert_eq!(workspace_selector("mail"), "name:mail");
+        assert_eq!(workspace_selector("name:mail"), "name:mail");
+        assert_eq!(workspace_selector("special:scratch"), "special:scratch");
+        assert_eq!(workspace_selector("r+1"), "name:r+1");
+        assert_eq!(workspace_selector("+1"), "name:+1");
+        let backend = mock();
+        quick()
+            .move_to_workspace(&backend, 1, "mail", true)
+            .unwrap();
+        backend.switch_workspace("mail").unwrap();
+        assert_eq!(
+            backend.dispatched(),
+            [
+                Dispatch::MoveToWorkspaceSilent(1, String::from("name:mail")),
+                Dispatch::Workspace(String::from("name:mail")),
+            ]
+        );
+        assert_eq!(backend.clients.borrow()[0].workspace.name, "mail");
+    }
+
+    #[test]
+    fn toggles_are_sent_once_while_hyprland_lags() {
+        let mut backend = mock();
+        backend.lag = 2;
+        act_on_window(&backend, 1, Action::Float, &quick()).unwrap();
+        act_on_window(&backend, 2, Action::Pin, &quick()).unwrap();
+        assert_eq!(
+            backend.dispatched(),
+            [Dispatch::ToggleFloating(1), Dispatch::TogglePin(2)]
+        );
+        let clients = backend.clients.borrow();
+        assert!(clients[0].floating && !clients[0].pinned);
+        assert!(clients[1].pinned && !clients[1].floating);
+    }
+}
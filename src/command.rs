@@ -0,0 +1,201 @@
+//! The command language of wint: whitespace-separated tokens, each a hint followed
+//! immediately by a nick from tilings.xml, e.g. `atex cpdf`.
+//!
+//! Positions are counted in characters, as expected by `gtk::Entry::select_region`.
+use std::fmt;
+
+/// Tile the window with this hint as this nick
+#[derive(Debug, Clone, PartialEq)]
+pub struct Assignment {
+    pub hint: String,
+    pub nick: String,
+    /// Where the nick is in the command, for reporting unknown nicks
+    pub nick_start: usize,
+    pub nick_end: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandError {
+    pub message: String,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl CommandError {
+    pub fn new<S: Into<String>>(message: S, start: usize, end: usize) -> CommandError {
+        CommandError {
+            message: message.into(),
+            start,
+            end,
+        }
+    }
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (at {})", self.message, self.start + 1)
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// Parse the command, given the hints currently shown
+pub fn parse_command<S: AsRef<str>>(
+    cmd: &str,
+    hints: &[S],
+) -> Result<Vec<Assignment>, CommandError> {
+    let chars = cmd.chars().collect::<Vec<char>>();
+    let mut assignments: Vec<Assignment> = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i].is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < chars.len() && !chars[i].is_whitespace() {
+            i += 1;
+        }
+        let assignment = parse_token(&chars[start..i], start, hints)?;
+        if assignments.iter().any(|a| a.hint == assignment.hint) {
+            return Err(CommandError::new(
+                format!(
+                    "window {} is already tiled in this command",
+                    assignment.hint
+                ),
+                start,
+                i,
+            ));
+        }
+        assignments.push(assignment);
+    }
+    if assignments.is_empty() {
+        return Err(CommandError::new(
+            "type a hint followed by a nick, e.g. atex",
+            0,
+            chars.len(),
+        ));
+    }
+    Ok(assignments)
+}
+
+fn parse_token<S: AsRef<str>>(
+    token: &[char],
+    start: usize,
+    hints: &[S],
+) -> Result<Assignment, CommandError> {
+    let end = start + token.len();
+    let is_hint = |p: &str| hints.iter().any(|h| h.as_ref() == p);
+    let hint_len = (1..=token.len()).find(|&n| is_hint(&token[..n].iter().collect::<String>()));
+    match hint_len {
+        Some(n) if n == token.len() => Err(CommandError::new(
+            format!("no nick after {}", token.iter().collect::<String>()),
+            start,
+            end,
+        )),
+        Some(n) => Ok(Assignment {
+            hint: token[..n].iter().collect(),
+            nick: token[n..].iter().collect(),
+            nick_start: start + n,
+            nick_end: end,
+        }),
+        None => {
+            let lowered = token.iter().collect::<String>().to_lowercase();
+            let message = if token[0].is_uppercase()
+                && (1..=lowered.len())
+                    .any(|n| lowered.is_char_boundary(n) && is_hint(&lowered[..n]))
+            {
+                String::from("hints are lowercase")
+            } else {
+                format!("no window has hint {}", token[0])
+            };
+            Err(CommandError::new(message, start, end))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HINTS: [&str; 3] = ["a", "b", "c"];
+
+    fn error(cmd: &str) -> CommandError {
+        parse_command(cmd, &HINTS).unwrap_err()
+    }
+
+    #[test]
+    fn double_space() {
+        let assignments = parse_command("atex  bpdf", &HINTS).unwrap();
+        assert_eq!(
+            assignments,
+            [
+                Assignment {
+                    hint: String::from("a"),
+                    nick: String::from("tex"),
+                    nick_start: 1,
+                    nick_end: 4,
+                },
+                Assignment {
+                    hint: String::from("b"),
+                    nick: String::from("pdf"),
+                    nick_start: 7,
+                    nick_end: 10,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn uppercase_hint() {
+        assert_eq!(
+            error("atex Bpdf"),
+            CommandError::new("hints are lowercase", 5, 9)
+        );
+    }
+
+    #[test]
+    fn unknown_hint() {
+        assert_eq!(
+            error("ztex"),
+            CommandError::new("no window has hint z", 0, 4)
+        );
+    }
+
+    #[test]
+    fn hint_without_nick() {
+        assert_eq!(error("atex b"), CommandError::new("no nick after b", 5, 6));
+    }
+
+    #[test]
+    fn duplicate_hint() {
+        assert_eq!(
+            error("atex apdf"),
+            CommandError::new("window a is already tiled in this command", 5, 9)
+        );
+    }
+
+    #[test]
+    fn empty_command() {
+        assert_eq!(error("  ").start, 0);
+        assert_eq!(error("  ").end, 2);
+    }
+
+    #[test]
+    fn ranges_are_in_characters() {
+        let assignments = parse_command("aé  bpdf", &HINTS).unwrap();
+        assert_eq!((assignments[1].nick_start, assignments[1].nick_end), (5, 8));
+        assert_eq!(
+            error("aé  zpdf"),
+            CommandError::new("no window has hint z", 4, 8)
+        );
+    }
+
+    #[test]
+    fn multi_letter_hints() {
+        let assignments = parse_command("abtex", &["aa", "ab"]).unwrap();
+        assert_eq!(assignments[0].hint, "ab");
+        assert_eq!(assignments[0].nick, "tex");
+        assert_eq!(assignments[0].nick_start, 2);
+    }
+}
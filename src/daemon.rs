@@ -0,0 +1,303 @@
+//! winterd follows Hyprland's event socket (`.socket2.sock`), so that the focus history
+//! includes the windows focused with the mouse or with Hyprland's own keybinds, and
+//! answers winj and wint over a local socket.
+//!
+//! A query is one line (`history`, `active` or `workspace`); the answer is sent back
+//! and the connection closed. `history` answers with one address per line, most
+//! recent first.
+use crate::history::History;
+use crate::hyprland::parse_hex_to_u64;
+use crate::state::Stored;
+use crate::{tmpfile_path, Backend, TMPFile, Window, WintError};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+/// How long winterd and its clients wait for each other, so that a client which
+/// never finishes its query cannot hold up the others
+pub const QUERY_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The events of `.socket2.sock` which winterd follows
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// `activewindowv2>>ADDRESS`, with an empty address when no window has the focus
+    ActiveWindow(Option<Window>),
+    /// `openwindow>>ADDRESS,WORKSPACE,CLASS,TITLE`
+    OpenWindow(Window),
+    /// `closewindow>>ADDRESS`
+    CloseWindow(Window),
+    /// `workspace>>NAME`
+    Workspace(String),
+}
+
+/// Parse a line of `.socket2.sock`; the events winterd does not follow give `None`
+pub fn parse_event(line: &str) -> Option<Event> {
+    let (name, data) = line.trim_end().split_once(">>")?;
+    let address = |s: &str| parse_hex_to_u64(s.split(',').next().unwrap_or("")).ok();
+    match name {
+        "activewindowv2" => Some(Event::ActiveWindow(address(data))),
+        "openwindow" => address(data).map(Event::OpenWindow),
+        "closewindow" => address(data).map(Event::CloseWindow),
+        "workspace" => Some(Event::Workspace(data.to_string())),
+        _ => None,
+    }
+}
+
+/// What winterd knows about the session
+#[derive(Debug, Clone, Default)]
+pub struct Tracker {
+    pub history: History,
+    /// The windows which are open
+    pub windows: Vec<Window>,
+    pub active: Option<Window>,
+    pub workspace: String,
+}
+
+impl Tracker {
+    pub fn new(history: History) -> Tracker {
+        Tracker {
+            history,
+            ..Default::default()
+        }
+    }
+
+    /// Start from the current state of the window manager
+    pub fn seed(&mut self, backend: &dyn Backend) -> Result<(), WintError> {
+        let clients = backend.clients()?;
+        self.windows = clients.iter().map(|c| c.address).collect();
+        self.history.prune(&clients);
+        self.active = backend.active_window()?.map(|c| c.address);
+        if let Some(w) = self.active {
+            self.history.push(w);
+        }
+        self.workspace = backend.active_workspace()?.name;
+        Ok(())
+    }
+
+    pub fn handle(&mut self, event: &Event) {
+        match *event {
+            Event::ActiveWindow(w) => {
+                self.active = w;
+                if let Some(w) = w {
+                    self.history.push(w);
+                }
+            }
+            Event::OpenWindow(w) => {
+                if !self.windows.contains(&w) {
+                    self.windows.push(w);
+                }
+            }
+            Event::CloseWindow(w) => {
+                self.windows.retain(|x| *x != w);
+                self.history.windows.retain(|x| *x != w);
+                if self.active == Some(w) {
+                    self.active = None;
+                }
+            }
+            Event::Workspace(ref name) => self.workspace = name.clone(),
+        }
+    }
+
+    /// The answer to a query
+    pub fn answer(&self, query: &str) -> String {
+        match query.trim() {
+            "history" => self
+                .history
+                .windows
+                .iter()
+                .map(|w| format!("{:#x}\n", w))
+                .collect(),
+            "active" => self
+                .active
+                .map(|w| format!("{:#x}\n", w))
+                .unwrap_or_default(),
+            "workspace" => format!("{}\n", self.workspace),
+            other => format!("error: unknown query {}\n", other),
+        }
+    }
+}
+
+/// Apply the events read from `events` until the stream ends
+pub fn follow_events<R: BufRead>(events: R, tracker: &Mutex<Tracker>) -> Result<(), WintError> {
+    for line in events.lines() {
+        let line = line.map_err(WintError::Socket)?;
+        if let Some(event) = parse_event(&line) {
+            tracker.lock().unwrap().handle(&event);
+        }
+    }
+    Ok(())
+}
+
+/// Connect to the event socket and follow it
+pub fn follow_socket(events: &Path, tracker: &Mutex<Tracker>) -> Result<(), WintError> {
+    let stream = UnixStream::connect(events).map_err(WintError::Socket)?;
+    follow_events(BufReader::new(stream), tracker)
+}
+
+fn answer(mut stream: UnixStream, tracker: &Mutex<Tracker>) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(QUERY_TIMEOUT))?;
+    stream.set_write_timeout(Some(QUERY_TIMEOUT))?;
+    let mut query = String::new();
+    BufReader::new(&stream).read_line(&mut query)?;
+    let answer = tracker.lock().unwrap().answer(&query);
+    stream.write_all(answer.as_bytes())
+}
+
+/// Answer the queries sent to the listener, forever; each connection has its own thread,
+/// so that a client which stalls does not keep the others waiting
+pub fn serve(listener: &UnixListener, tracker: &Mutex<Tracker>) {
+    thread::scope(|scope| {
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    scope.spawn(move || {
+                        if let Err(e) = answer(stream, tracker) {
+                            println!("ERROR: winterd query: {}", e);
+                        }
+                    });
+                }
+                Err(e) => println!("ERROR: winterd query: {}", e),
+            }
+        }
+    });
+}
+
+/// The socket where winterd answers, next to the tmpfile
+pub fn socket_path(tmpfile: &TMPFile) -> PathBuf {
+    let mut p = tmpfile_path(tmpfile).into_os_string();
+    p.push("-winterd.sock");
+    PathBuf::from(p)
+}
+
+/// Ask winterd
+pub fn query(socket: &Path, query: &str) -> Result<String, WintError> {
+    let mut stream = UnixStream::connect(socket).map_err(WintError::Daemon)?;
+    stream
+        .set_read_timeout(Some(QUERY_TIMEOUT))
+        .and_then(|_| stream.set_write_timeout(Some(QUERY_TIMEOUT)))
+        .map_err(WintError::Daemon)?;
+    stream
+        .write_all(format!("{}\n", query).as_bytes())
+        .map_err(WintError::Daemon)?;
+    stream
+        .shutdown(Shutdown::Write)
+        .map_err(WintError::Daemon)?;
+    let mut answer = String::new();
+    stream
+        .read_to_string(&mut answer)
+        .map_err(WintError::Daemon)?;
+    match answer.strip_prefix("error: ") {
+        Some(e) => Err(WintError::Daemon(std::io::Error::other(
+            e.trim().to_string(),
+        ))),
+        None => Ok(answer),
+    }
+}
+
+/// The focus history kept by winterd
+pub fn history(socket: &Path, limit: usize) -> Result<History, WintError> {
+    let mut history = History::new(limit);
+    for line in query(socket, "history")?.lines() {
+        if let Ok(w) = parse_hex_to_u64(line.trim()) {
+            history.windows.push(w);
+        }
+    }
+    history.windows.truncate(limit);
+    Ok(history)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    fn follow(events: &str, tracker: Tracker) -> Tracker {
+        let tracker = Mutex::new(tracker);
+        follow_events(Cursor::new(events), &tracker).unwrap();
+        tracker.into_inner().unwrap()
+    }
+
+    #[test]
+    fn events() {
+        assert_eq!(
+            parse_event("activewindowv2>>55d0c3a0"),
+            Some(Event::ActiveWindow(Some(0x55d0c3a0)))
+        );
+        assert_eq!(
+            parse_event("activewindowv2>>"),
+            Some(Event::ActiveWindow(None))
+        );
+        assert_eq!(
+            parse_event("openwindow>>55d0c3a0,2,kitty,htop, with a comma"),
+            Some(Event::OpenWindow(0x55d0c3a0))
+        );
+        assert_eq!(
+            parse_event("workspace>>special:scratch"),
+            Some(Event::Workspace(String::from("special:scratch")))
+        );
+        assert_eq!(parse_event("activewindow>>kitty,htop"), None);
+        assert_eq!(parse_event("garbage"), None);
+    }
+
+    #[test]
+    fn focus_history() {
+        let tracker = follow(
+            "openwindow>>a1,1,kitty,htop\n\
+             openwindow>>b2,1,firefox,docs\n\
+             activewindowv2>>a1\n\
+             activewindow>>kitty,htop\n\
+             activewindowv2>>b2\n\
+             workspace>>2\n\
+             activewindowv2>>\n",
+            Tracker::new(History::new(10)),
+        );
+        assert_eq!(tracker.answer("history"), "0xb2\n0xa1\n");
+        assert_eq!(tracker.answer("active"), "");
+        assert_eq!(tracker.answer("workspace"), "2\n");
+        assert_eq!(tracker.windows, [0xa1, 0xb2]);
+    }
+
+    #[test]
+    fn closed_windows_are_forgotten() {
+        let tracker = follow(
+            "activewindowv2>>a1\n\
+             activewindowv2>>b2\n\
+             activewindowv2>>c3\n\
+             closewindow>>c3\n\
+             activewindowv2>>a1\n",
+            Tracker::new(History::new(10)),
+        );
+        assert_eq!(tracker.answer("history"), "0xa1\n0xb2\n");
+        assert_eq!(tracker.answer("active"), "0xa1\n");
+        assert_eq!(
+            tracker.answer("windows\n"),
+            "error: unknown query windows\n"
+        );
+    }
+
+    #[test]
+    fn stalled_client_does_not_block_queries() {
+        let socket = std::env::temp_dir().join(format!(
+            "hyprwinter-test-{}-winterd.sock",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        let tracker = Arc::new(Mutex::new(follow(
+            "activewindowv2>>a1\n",
+            Tracker::new(History::new(10)),
+        )));
+        thread::spawn(move || serve(&listener, &tracker));
+        // connects and never sends a full line, until the query has been answered
+        let mut stalled = UnixStream::connect(&socket).unwrap();
+        stalled.write_all(b"hist").unwrap();
+        assert_eq!(history(&socket, 10).unwrap().windows, [0xa1]);
+        drop(stalled);
+        let _ = std::fs::remove_file(&socket);
+    }
+}
@@ -0,0 +1,153 @@
+//! Fuzzy matching for the search mode of winj: the characters of each typed word must
+//! appear in order in the title or the class of the window, though not necessarily
+//! next to each other. Matches at the start of words and runs of consecutive
+//! characters score higher.
+use crate::Client;
+use std::cmp::Reverse;
+
+const MATCH: i64 = 16;
+const CONSECUTIVE: i64 = 8;
+const WORD_START: i64 = 8;
+const GAP: i64 = 1;
+
+/// How well `pattern` matches `text`, or `None` if it does not match.
+///
+/// Case is ignored unless the pattern contains an uppercase letter.
+pub fn score(pattern: &str, text: &str) -> Option<i64> {
+    let case_sensitive = pattern.chars().any(char::is_uppercase);
+    let norm = |c: char| {
+        if case_sensitive {
+            c
+        } else {
+            c.to_lowercase().next().unwrap_or(c)
+        }
+    };
+    let pattern = pattern.chars().map(norm).collect::<Vec<char>>();
+    let raw = text.chars().collect::<Vec<char>>();
+    let text = raw.iter().map(|&c| norm(c)).collect::<Vec<char>>();
+    if pattern.is_empty() {
+        return Some(0);
+    }
+    (0..text.len())
+        .filter(|&start| text[start] == pattern[0])
+        .filter_map(|start| score_from(&pattern, &text, &raw, start))
+        .max()
+}
+
+/// The score of the match whose first character is at `start`, taking each following
+/// character as early as possible
+fn score_from(pattern: &[char], text: &[char], raw: &[char], start: usize) -> Option<i64> {
+    let mut score = -GAP * start as i64;
+    let mut prev: Option<usize> = None;
+    let mut i = start;
+    for &p in pattern {
+        while i < text.len() && text[i] != p {
+            i += 1;
+        }
+        if i == text.len() {
+            return None;
+        }
+        score += MATCH;
+        match prev {
+            Some(j) if j + 1 == i => score += CONSECUTIVE,
+            Some(j) => score -= GAP * (i - j - 1) as i64,
+            None => (),
+        }
+        if i == 0
+            || !raw[i - 1].is_alphanumeric()
+            || (raw[i - 1].is_lowercase() && raw[i].is_uppercase())
+        {
+            score += WORD_START;
+        }
+        prev = Some(i);
+        i += 1;
+    }
+    Some(score)
+}
+
+/// How well `pattern` matches the window: each word of the pattern has to match
+/// its title or its class
+pub fn client_score(pattern: &str, client: &Client) -> Option<i64> {
+    pattern
+        .split_whitespace()
+        .map(|word| {
+            [score(word, &client.title), score(word, &client.class)]
+                .into_iter()
+                .flatten()
+                .max()
+        })
+        .sum()
+}
+
+/// The windows matching `pattern`, best first; windows with equal scores keep their order
+pub fn rank<'a>(pattern: &str, clients: &[&'a Client]) -> Vec<&'a Client> {
+    let mut scored = clients
+        .iter()
+        .filter_map(|&c| client_score(pattern, c).map(|s| (s, c)))
+        .collect::<Vec<(i64, &Client)>>();
+    scored.sort_by_key(|&(s, _)| Reverse(s));
+    scored.into_iter().map(|(_, c)| c).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::client;
+
+    #[test]
+    fn characters_in_order() {
+        assert!(score("fb", "foo bar").is_some());
+        assert!(score("bf", "foo bar").is_none());
+        assert_eq!(score("", "anything"), Some(0));
+    }
+
+    #[test]
+    fn word_starts_score_higher() {
+        // "b" starts a word in "foo bar" but not in "foobar"
+        assert!(score("b", "foo bar") > score("b", "foobar"));
+        // as does an uppercase letter after a lowercase one
+        assert!(score("b", "fooBar") > score("b", "foobar"));
+    }
+
+    #[test]
+    fn consecutive_characters_score_higher() {
+        assert!(score("fir", "firefox") > score("fir", "f i r"));
+        assert!(score("ox", "firefox") > score("ox", "forex"));
+    }
+
+    #[test]
+    fn smart_case() {
+        assert!(score("firefox", "Firefox").is_some());
+        assert!(score("Firefox", "Firefox").is_some());
+        assert!(score("FireFox", "Firefox").is_none());
+    }
+
+    #[test]
+    fn words_match_title_or_class() {
+        let c = client(1, "org.pwmt.zathura", "thesis.pdf");
+        assert!(client_score("zat thesis", &c).is_some());
+        assert!(client_score("thesis zat", &c).is_some());
+        assert!(client_score("zat notes", &c).is_none());
+    }
+
+    #[test]
+    fn equal_scores_keep_their_order() {
+        let clients = [
+            client(1, "kitty", "vim"),
+            client(2, "firefox", "docs"),
+            client(3, "kitty", "htop"),
+            client(4, "kitty", "less"),
+        ];
+        let refs = clients.iter().collect::<Vec<&Client>>();
+        let ranked = rank("kitty", &refs)
+            .iter()
+            .map(|c| c.address)
+            .collect::<Vec<u64>>();
+        assert_eq!(ranked, [1, 3, 4]);
+        let ranked = rank("kitty htop", &refs)
+            .iter()
+            .map(|c| c.address)
+            .collect::<Vec<u64>>();
+        assert_eq!(ranked, [3]);
+    }
+}
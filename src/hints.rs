@@ -0,0 +1,368 @@
+//! Hints are the letters shown next to the windows. When there are more windows than
+//! letters, some hints get longer, vimium style; no hint is a prefix of another, so
+//! a hint is complete as soon as it has been typed.
+use crate::history::History;
+use crate::hyprland::parse_hex_to_u64;
+use crate::script::WindowMatch;
+use crate::state::{self, Stored};
+use crate::{Client, Window, WintError};
+use std::collections::HashMap;
+use std::path::Path;
+
+/// At least `n` prefix-free hints, as short as possible, in the order of the alphabet.
+///
+/// The last single letters are the first to be extended to two letters, so that the
+/// windows at the top of the list keep single-letter hints.
+pub fn hint_pool(alphabet: &[char], n: usize) -> Vec<String> {
+    let mut leaves = alphabet
+        .iter()
+        .map(|c| c.to_string())
+        .collect::<Vec<String>>();
+    if alphabet.len() < 2 {
+        return leaves;
+    }
+    while leaves.len() < n {
+        let shortest = leaves.iter().map(|h| h.chars().count()).min().unwrap_or(0);
+        let i = leaves
+            .iter()
+            .rposition(|h| h.chars().count() == shortest)
+            .unwrap_or(0);
+        let prefix = leaves.remove(i);
+        let children = alphabet.iter().map(|c| format!("{}{}", prefix, c));
+        leaves.splice(i..i, children);
+    }
+    leaves
+}
+
+/// Hints for `n` windows
+pub fn generate_hints(alphabet: &[char], n: usize) -> Vec<String> {
+    let mut hints = hint_pool(alphabet, n);
+    hints.truncate(n);
+    hints
+}
+
+/// The default alphabet: `a` to `z`
+pub fn default_alphabet() -> Vec<char> {
+    ('a'..='z').collect()
+}
+
+/// How the windows are sorted before they get their hints
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum HintOrder {
+    /// As listed by Hyprland
+    #[default]
+    #[serde(rename = "list")]
+    List,
+    /// By workspace, then as listed by Hyprland
+    #[serde(rename = "workspace")]
+    Workspace,
+    /// By class, then as listed by Hyprland
+    #[serde(rename = "class")]
+    Class,
+    /// Most recently used first, then as listed by Hyprland
+    #[serde(rename = "mru")]
+    Mru,
+}
+
+/// The `<hints>` section of config.xml
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct HintsConfig {
+    pub alphabet: String,
+    pub order: HintOrder,
+    /// Letters of the alphabet not to use
+    pub exclude: String,
+    /// When present, windows keep their hints between runs
+    pub sticky: Option<StickyHints>,
+}
+
+impl Default for HintsConfig {
+    fn default() -> HintsConfig {
+        HintsConfig {
+            alphabet: default_alphabet().into_iter().collect(),
+            order: HintOrder::default(),
+            exclude: String::new(),
+            sticky: None,
+        }
+    }
+}
+
+impl HintsConfig {
+    /// The letters to build hints from; digits are left out since winj uses them for workspaces.
+    /// Hints cannot be built from fewer than two letters, so then `a` to `z` are used instead.
+    pub fn letters(&self) -> Vec<char> {
+        let mut letters: Vec<char> = Vec::new();
+        for c in self.alphabet.chars() {
+            if !c.is_whitespace()
+                && !c.is_ascii_digit()
+                && !self.exclude.contains(c)
+                && !letters.contains(&c)
+            {
+                letters.push(c);
+            }
+        }
+        if letters.len() < 2 {
+            return default_alphabet();
+        }
+        letters
+    }
+
+    /// Sort the windows and give each one its hint.
+    ///
+    /// In sticky mode (with a `store`), a window keeps the hint it had last time, a new
+    /// window gets its preferred letter from `<sticky>` if free, and otherwise the first
+    /// free hint; the store is updated with the result.
+    pub fn assign<'a>(
+        &self,
+        mut clients: Vec<&'a Client>,
+        store: Option<&mut HintStore>,
+        history: &History,
+    ) -> Vec<(String, &'a Client)> {
+        match self.order {
+            HintOrder::List => (),
+            HintOrder::Workspace => clients.sort_by_key(|c| c.workspace.id),
+            HintOrder::Class => clients.sort_by(|a, b| a.class.cmp(&b.class)),
+            HintOrder::Mru => clients.sort_by_key(|c| history.rank(c.address)),
+        }
+        let (store, sticky) = match (store, &self.sticky) {
+            (Some(store), Some(sticky)) => (store, sticky),
+            _ => {
+                let hints = generate_hints(&self.letters(), clients.len());
+                return hints.into_iter().zip(clients).collect();
+            }
+        };
+        let pool = hint_pool(&self.letters(), clients.len());
+        let mut assigned: Vec<Option<String>> = vec![None; clients.len()];
+        let is_free = |h: &String, assigned: &[Option<String>]| {
+            pool.contains(h) && !assigned.iter().any(|a| a.as_ref() == Some(h))
+        };
+        for (i, client) in clients.iter().enumerate() {
+            if let Some(h) = store.hints.get(&client.address) {
+                if is_free(h, &assigned) {
+                    assigned[i] = Some(h.clone());
+                }
+            }
+        }
+        let rules = sticky.rules();
+        for (i, client) in clients.iter().enumerate() {
+            if assigned[i].is_some() {
+                continue;
+            }
+            if let Some(&(_, h)) = rules.iter().find(|(m, _)| m.matches(client)) {
+                if is_free(h, &assigned) {
+                    assigned[i] = Some(h.clone());
+                }
+            }
+        }
+        // hints of the windows which are not shown are left to them while possible
+        let reserved = store
+            .hints
+            .iter()
+            .filter(|(w, _)| !clients.iter().any(|c| c.address == **w))
+            .map(|(_, h)| h.clone())
+            .collect::<Vec<String>>();
+        for i in 0..clients.len() {
+            if assigned[i].is_some() {
+                continue;
+            }
+            let free = pool
+                .iter()
+                .filter(|h| is_free(h, &assigned))
+                .collect::<Vec<&String>>();
+            assigned[i] = free
+                .iter()
+                .find(|h| !reserved.contains(h))
+                .or(free.first())
+                .map(|h| h.to_string());
+        }
+        let mut result: Vec<(String, &'a Client)> = Vec::new();
+        for (hint, client) in assigned.into_iter().zip(clients) {
+            if let Some(hint) = hint {
+                store.hints.insert(client.address, hint.clone());
+                result.push((hint, client));
+            }
+        }
+        result
+    }
+}
+
+/// A preferred letter in the `<sticky>` section: for the windows of this class,
+/// or whose title matches this regex
+#[derive(Debug, Deserialize, Clone)]
+pub struct StickyItem {
+    #[serde(default)]
+    pub class: Option<String>,
+    #[serde(default)]
+    pub title: Option<String>,
+    pub letter: String,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct StickyHints {
+    #[serde(default)]
+    pub item: Vec<StickyItem>,
+}
+
+impl StickyHints {
+    /// The preferred letter of each item, with the windows it is for
+    fn rules(&self) -> Vec<(WindowMatch, &String)> {
+        self.item
+            .iter()
+            .filter_map(|item| {
+                match WindowMatch::new(item.class.as_deref(), item.title.as_deref()) {
+                    Ok(m) => Some((m, &item.letter)),
+                    Err(e) => {
+                        eprintln!("ERROR: sticky hint {}: {}", item.letter, e);
+                        None
+                    }
+                }
+            })
+            .collect()
+    }
+}
+
+/// The hints of the windows, kept between runs in sticky mode;
+/// stored one window per line as `0xADDRESS HINT`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HintStore {
+    pub hints: HashMap<Window, String>,
+}
+
+impl HintStore {
+    pub fn load(path: &Path) -> HintStore {
+        HintStore::default().loaded(path)
+    }
+
+    /// Assign hints in the store kept at `path`, see [`state::update`]
+    pub fn update<F>(path: &Path, change: F) -> Result<HintStore, WintError>
+    where
+        F: FnOnce(&mut HintStore),
+    {
+        state::update(path, HintStore::default(), change)
+    }
+}
+
+impl Stored for HintStore {
+    fn read(&mut self, contents: &str) {
+        for line in contents.lines() {
+            let mut fields = line.split_whitespace();
+            if let (Some(address), Some(hint)) = (fields.next(), fields.next()) {
+                if let Ok(w) = parse_hex_to_u64(address) {
+                    self.hints.insert(w, hint.to_string());
+                }
+            }
+        }
+    }
+
+    fn contents(&self) -> String {
+        let mut lines = self
+            .hints
+            .iter()
+            .map(|(w, h)| format!("{:#x} {}\n", w, h))
+            .collect::<Vec<String>>();
+        lines.sort();
+        lines.concat()
+    }
+
+    fn retain_windows(&mut self, keep: impl Fn(Window) -> bool) {
+        self.hints.retain(|w, _| keep(*w));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::client;
+
+    fn hints_config(alphabet: &str, exclude: &str) -> HintsConfig {
+        HintsConfig {
+            alphabet: alphabet.to_string(),
+            exclude: exclude.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn prefix_free_hints() {
+        let hints = generate_hints(&['a', 'b', 'c'], 5);
+        assert_eq!(hints, ["a", "b", "ca", "cb", "cc"]);
+        for h in &hints {
+            assert!(!hints.iter().any(|o| o != h && o.starts_with(h.as_str())));
+        }
+    }
+
+    #[test]
+    fn too_few_letters_fall_back_to_the_default_alphabet() {
+        assert_eq!(hints_config("ab", "ab").letters(), default_alphabet());
+        assert_eq!(hints_config("a123", "").letters(), default_alphabet());
+        assert_eq!(hints_config("asdf", "d").letters(), ['a', 's', 'f']);
+    }
+
+    #[test]
+    fn every_window_gets_a_hint() {
+        let clients = (1..=30)
+            .map(|address| client(address, "", ""))
+            .collect::<Vec<Client>>();
+        let hinted =
+            hints_config("x", "").assign(clients.iter().collect(), None, &History::default());
+        assert_eq!(hinted.len(), 30);
+    }
+
+    #[test]
+    fn sticky_letters_by_class_or_title() {
+        let item = |class: Option<&str>, title: Option<&str>, letter: &str| StickyItem {
+            class: class.map(String::from),
+            title: title.map(String::from),
+            letter: letter.to_string(),
+        };
+        let conf = HintsConfig {
+            sticky: Some(StickyHints {
+                item: vec![
+                    item(Some("emacs"), None, "e"),
+                    item(None, Some("YouTube"), "y"),
+                    item(None, Some("(unclosed"), "u"),
+                ],
+            }),
+            ..Default::default()
+        };
+        let clients = [
+            client(1, "kitty", "htop"),
+            client(2, "firefox", "Music - YouTube"),
+            client(3, "emacs", "notes.org"),
+        ];
+        let mut store = HintStore::default();
+        let hinted = conf.assign(
+            clients.iter().collect(),
+            Some(&mut store),
+            &History::default(),
+        );
+        let hints = hinted
+            .iter()
+            .map(|(h, c)| (c.address, h.as_str()))
+            .collect::<Vec<(Window, &str)>>();
+        assert_eq!(hints, [(1, "a"), (2, "y"), (3, "e")]);
+        assert_eq!(store.hints[&2], "y");
+    }
+
+    #[test]
+    fn store_updates_keep_each_other() {
+        let path =
+            std::env::temp_dir().join(format!("hyprwinter-test-{}-hints", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        HintStore::update(&path, |store| {
+            store.hints.insert(0xa1, String::from("e"));
+        })
+        .unwrap();
+        let store = HintStore::update(&path, |store| {
+            store.hints.insert(0xb2, String::from("f"));
+        })
+        .unwrap();
+        assert_eq!(store, HintStore::load(&path));
+        assert_eq!(store.hints.len(), 2);
+        assert_eq!(store.hints[&0xa1], "e");
+        let _ = std::fs::remove_file(&path);
+        let mut lock = path.into_os_string();
+        lock.push(".lock");
+        let _ = std::fs::remove_file(lock);
+    }
+}
@@ -0,0 +1,91 @@
+//! The windows winj has jumped between, most recent first. They are kept in the tmpfile,
+//! one address per line, so a file written by older versions (a single address) is
+//! read as a history of one window.
+use crate::hyprland::parse_hex_to_u64;
+use crate::state::{self, Stored};
+use crate::{Window, WintError};
+use std::path::Path;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct History {
+    /// Most recent first
+    pub windows: Vec<Window>,
+    /// How many windows to remember
+    pub limit: usize,
+}
+
+impl History {
+    pub fn new(limit: usize) -> History {
+        History {
+            windows: Vec::new(),
+            limit,
+        }
+    }
+
+    /// The last `limit` windows of the history kept at `path`
+    pub fn load(path: &Path, limit: usize) -> History {
+        History::new(limit).loaded(path)
+    }
+
+    /// Record a change in the history kept at `path`, see [`state::update`]
+    pub fn update<F>(path: &Path, limit: usize, change: F) -> Result<History, WintError>
+    where
+        F: FnOnce(&mut History),
+    {
+        state::update(path, History::new(limit), change)
+    }
+
+    /// Put the window on top of the history
+    pub fn push(&mut self, win: Window) {
+        if win == 0 {
+            return;
+        }
+        self.windows.retain(|w| *w != win);
+        self.windows.insert(0, win);
+        self.windows.truncate(self.limit);
+    }
+
+    /// Record a jump from the active window to `target`
+    pub fn jump(&mut self, active: Window, target: Window) {
+        self.push(active);
+        self.push(target);
+    }
+
+    /// The windows to go back to from `active`, most recent first
+    pub fn previous(&self, active: Window) -> Vec<Window> {
+        self.windows
+            .iter()
+            .filter(|w| **w != active)
+            .cloned()
+            .collect()
+    }
+
+    /// Where the window is in the history, for sorting; windows not in the history come last
+    pub fn rank(&self, win: Window) -> usize {
+        self.windows
+            .iter()
+            .position(|w| *w == win)
+            .unwrap_or(self.windows.len())
+    }
+}
+
+impl Stored for History {
+    fn read(&mut self, contents: &str) {
+        for line in contents.lines() {
+            if let Ok(w) = parse_hex_to_u64(line.trim()) {
+                if !self.windows.contains(&w) {
+                    self.windows.push(w);
+                }
+            }
+        }
+        self.windows.truncate(self.limit);
+    }
+
+    fn contents(&self) -> String {
+        self.windows.iter().map(|w| format!("{:#x}\n", w)).collect()
+    }
+
+    fn retain_windows(&mut self, keep: impl Fn(Window) -> bool) {
+        self.windows.retain(|w| keep(*w));
+    }
+}
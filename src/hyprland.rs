@@ -0,0 +1,414 @@
+//! Native client for the Hyprland request socket
+//! (`$XDG_RUNTIME_DIR/hypr/$HYPRLAND_INSTANCE_SIGNATURE/.socket.sock`).
+//!
+//! Each request opens a fresh connection, writes the command and reads the reply
+//! until the compositor closes the stream, which is what `hyprctl` does as well.
+use crate::{Window, WintError};
+use serde::de::{DeserializeOwned, Deserializer};
+use serde::Deserialize;
+use serde_json::Value;
+use std::io::{Read, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+
+pub fn parse_hex_to_u64(hex_str: &str) -> Result<u64, std::num::ParseIntError> {
+    // Ensure the string starts with "0x" and strip it
+    let trimmed = hex_str.trim_start_matches("0x");
+    // Parse the remaining part as a hexadecimal number
+    u64::from_str_radix(trimmed, 16)
+}
+
+fn deserialize_address<'de, D>(deserializer: D) -> Result<Window, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    parse_hex_to_u64(&s).map_err(serde::de::Error::custom)
+}
+
+fn deserialize_addresses<'de, D>(deserializer: D) -> Result<Vec<Window>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|s| parse_hex_to_u64(s).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+/// Older Hyprland versions report `fullscreen` as a bool, newer ones as the mode number
+fn deserialize_fullscreen<'de, D>(deserializer: D) -> Result<u8, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::Bool(b) => Ok(if b { 2 } else { 0 }),
+        Value::Number(n) => Ok(n.as_u64().unwrap_or(0) as u8),
+        other => Err(serde::de::Error::custom(format!(
+            "unexpected fullscreen value {}",
+            other
+        ))),
+    }
+}
+
+/// The `workspace` field of clients and `activeWorkspace` field of monitors
+#[derive(Debug, Deserialize, Clone, Default, PartialEq)]
+pub struct WorkspaceRef {
+    pub id: i64,
+    pub name: String,
+}
+
+/// An entry of `j/workspaces`, also the reply to `j/activeworkspace`
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Workspace {
+    pub id: i64,
+    pub name: String,
+    pub monitor: String,
+    #[serde(rename = "monitorID")]
+    pub monitor_id: i64,
+    pub windows: u32,
+    #[serde(rename = "hasfullscreen")]
+    pub has_fullscreen: bool,
+    #[serde(rename = "lastwindow", deserialize_with = "deserialize_address")]
+    pub last_window: Window,
+    #[serde(rename = "lastwindowtitle")]
+    pub last_window_title: String,
+}
+
+/// An entry of `j/clients`, also the reply to `j/activewindow`
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Client {
+    #[serde(deserialize_with = "deserialize_address")]
+    pub address: Window,
+    pub mapped: bool,
+    pub hidden: bool,
+    /// Position of the top left corner, in layout coordinates
+    pub at: [i32; 2],
+    pub size: [i32; 2],
+    pub workspace: WorkspaceRef,
+    pub floating: bool,
+    pub pseudo: bool,
+    /// The `id` of the monitor
+    pub monitor: i64,
+    pub class: String,
+    pub title: String,
+    #[serde(rename = "initialClass")]
+    pub initial_class: String,
+    #[serde(rename = "initialTitle")]
+    pub initial_title: String,
+    pub pid: i64,
+    pub xwayland: bool,
+    pub pinned: bool,
+    /// 0 for none, 1 for maximized, 2 for fullscreen
+    #[serde(deserialize_with = "deserialize_fullscreen")]
+    pub fullscreen: u8,
+    /// Addresses of the windows in the same group, empty when not grouped
+    #[serde(rename = "grouped", deserialize_with = "deserialize_addresses")]
+    pub group: Vec<Window>,
+    #[serde(rename = "focusHistoryID")]
+    pub focus_history_id: i32,
+}
+
+/// An entry of `j/monitors`
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct Monitor {
+    pub id: i64,
+    pub name: String,
+    pub description: String,
+    /// Resolution in physical pixels, before the transform is applied
+    pub width: u32,
+    pub height: u32,
+    #[serde(rename = "refreshRate")]
+    pub refresh_rate: f32,
+    /// Offset of the monitor, in layout coordinates
+    pub x: i32,
+    pub y: i32,
+    #[serde(rename = "activeWorkspace")]
+    pub active_workspace: WorkspaceRef,
+    #[serde(rename = "specialWorkspace")]
+    pub special_workspace: WorkspaceRef,
+    /// Space taken by bars: left, top, right, bottom
+    pub reserved: [i32; 4],
+    pub scale: f32,
+    pub transform: i32,
+    pub focused: bool,
+    pub disabled: bool,
+}
+
+/// The reply to `j/getoption`; which value is present depends on the type of the option
+#[derive(Debug, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct HyprOption {
+    pub option: String,
+    pub int: Option<i64>,
+    pub float: Option<f64>,
+    #[serde(rename = "str")]
+    pub string: Option<String>,
+    pub custom: Option<String>,
+    pub set: bool,
+}
+
+impl HyprOption {
+    /// The four sides of a gaps-like option, in CSS order: top, right, bottom, left
+    pub fn sides(&self) -> [i32; 4] {
+        if let Some(i) = self.int {
+            return [i as i32; 4];
+        }
+        let v = self
+            .custom
+            .as_deref()
+            .unwrap_or("")
+            .split_whitespace()
+            .filter_map(|x| x.parse::<i32>().ok())
+            .collect::<Vec<i32>>();
+        match v[..] {
+            [a] => [a; 4],
+            [a, b] => [a, b, a, b],
+            [a, b, c] => [a, b, c, b],
+            [a, b, c, d, ..] => [a, b, c, d],
+            _ => [0; 4],
+        }
+    }
+}
+
+impl Monitor {
+    /// Transforms 1, 3, 5 and 7 rotate the output by 90 or 270 degrees
+    pub fn is_rotated(&self) -> bool {
+        self.transform % 2 == 1
+    }
+
+    /// Width and height in physical pixels as seen by the user, i.e. after the transform
+    pub fn size(&self) -> (u32, u32) {
+        if self.is_rotated() {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        }
+    }
+
+    /// Resolution as seen by the user, e.g. `1080x1920` for a portrait monitor
+    pub fn resolution(&self) -> String {
+        let (width, height) = self.size();
+        format!("{}x{}", width, height)
+    }
+}
+
+/// The monitor showing the client
+pub fn monitor_of<'a>(monitors: &'a [Monitor], client: &Client) -> Option<&'a Monitor> {
+    monitors.iter().find(|m| m.id == client.monitor)
+}
+
+pub struct Hyprland {
+    socket: PathBuf,
+}
+
+impl Hyprland {
+    /// Talk to the request socket at the given path
+    pub fn new<P: Into<PathBuf>>(socket: P) -> Hyprland {
+        Hyprland {
+            socket: socket.into(),
+        }
+    }
+
+    /// Locate the request socket of the running Hyprland instance
+    pub fn from_env() -> Result<Hyprland, WintError> {
+        Ok(Hyprland::new(instance_dir()?.join(".socket.sock")))
+    }
+
+    pub fn socket(&self) -> &Path {
+        &self.socket
+    }
+
+    /// The event socket of the same instance
+    pub fn events_socket(&self) -> PathBuf {
+        self.socket.with_file_name(".socket2.sock")
+    }
+
+    /// Send a raw request (e.g. `j/clients`) and return the reply
+    pub fn request(&self, cmd: &str) -> Result<String, WintError> {
+        let mut stream = UnixStream::connect(&self.socket).map_err(WintError::Socket)?;
+        stream
+            .write_all(cmd.as_bytes())
+            .map_err(WintError::Socket)?;
+        stream
+            .shutdown(Shutdown::Write)
+            .map_err(WintError::Socket)?;
+        let mut reply = String::new();
+        stream
+            .read_to_string(&mut reply)
+            .map_err(WintError::Socket)?;
+        Ok(reply)
+    }
+
+    fn query<T: DeserializeOwned>(&self, what: &str) -> Result<T, WintError> {
+        let reply = self.request(&format!("j/{}", what))?;
+        serde_json::from_str(&reply).map_err(|_| WintError::Hyprland(reply))
+    }
+
+    pub fn clients(&self) -> Result<Vec<Client>, WintError> {
+        self.query("clients")
+    }
+
+    pub fn monitors(&self) -> Result<Vec<Monitor>, WintError> {
+        self.query("monitors")
+    }
+
+    pub fn active_workspace(&self) -> Result<Workspace, WintError> {
+        self.query("activeworkspace")
+    }
+
+    /// e.g. `option("general:border_size")`
+    pub fn option(&self, name: &str) -> Result<HyprOption, WintError> {
+        self.query(&format!("getoption {}", name))
+    }
+
+    /// Hyprland answers `{}` when no window has the focus
+    pub fn active_window(&self) -> Result<Option<Client>, WintError> {
+        let window: Value = self.query("activewindow")?;
+        match window.as_object() {
+            Some(obj) if !obj.is_empty() => Ok(Some(serde_json::from_value(window)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// Run a dispatcher, e.g. `dispatch("focuswindow address:0x1234")`
+    pub fn dispatch(&self, args: &str) -> Result<(), WintError> {
+        let reply = self.request(&format!("dispatch {}", args))?;
+        if reply.trim() == "ok" {
+            Ok(())
+        } else {
+            Err(WintError::Hyprland(reply))
+        }
+    }
+
+    /// Run several dispatchers in one `[[BATCH]]` request, so that Hyprland applies them together
+    pub fn dispatch_batch(&self, args: &[String]) -> Result<(), WintError> {
+        if args.is_empty() {
+            return Ok(());
+        }
+        let cmd = args
+            .iter()
+            .map(|a| format!("dispatch {}", a))
+            .collect::<Vec<String>>()
+            .join(";");
+        let reply = self.request(&format!("[[BATCH]]{}", cmd))?;
+        // Hyprland concatenates the replies to the individual requests
+        if reply.split_whitespace().all(|r| r == "ok") {
+            Ok(())
+        } else {
+            Err(WintError::Hyprland(reply))
+        }
+    }
+}
+
+/// The runtime directory of the running Hyprland instance
+pub fn instance_dir() -> Result<PathBuf, WintError> {
+    let signature =
+        std::env::var("HYPRLAND_INSTANCE_SIGNATURE").map_err(|_| WintError::NoHyprland)?;
+    let in_runtime = std::env::var("XDG_RUNTIME_DIR")
+        .map(|dir| Path::new(&dir).join("hypr").join(&signature))
+        .ok()
+        .filter(|p| p.exists());
+    // Hyprland versions before 0.40 kept their sockets under /tmp
+    Ok(in_runtime.unwrap_or_else(|| Path::new("/tmp/hypr").join(&signature)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread::{self, JoinHandle};
+
+    /// A stand-in for the request socket: answers each connection with the next reply,
+    /// and returns the requests it received
+    fn replay(replies: &[&str]) -> (Hyprland, JoinHandle<Vec<String>>) {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let socket = std::env::temp_dir().join(format!(
+            "hyprwinter-test-{}-{}.sock",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = std::fs::remove_file(&socket);
+        let listener = UnixListener::bind(&socket).unwrap();
+        let replies = replies
+            .iter()
+            .map(|r| r.to_string())
+            .collect::<Vec<String>>();
+        let path = socket.clone();
+        let server = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for reply in replies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = String::new();
+                stream.read_to_string(&mut request).unwrap();
+                stream.write_all(reply.as_bytes()).unwrap();
+                requests.push(request);
+            }
+            let _ = std::fs::remove_file(&path);
+            requests
+        });
+        (Hyprland::new(socket), server)
+    }
+
+    #[test]
+    fn clients_on_special_workspace() {
+        let (hypr, server) = replay(&[r#"[{
+            "address": "0x55d0c3a0", "mapped": true, "at": [10, 20], "size": [800, 600],
+            "workspace": {"id": -98, "name": "special:scratch"},
+            "floating": false, "monitor": 0, "class": "kitty", "title": "htop",
+            "fullscreen": false, "grouped": [], "focusHistoryID": 1
+        }]"#]);
+        let clients = hypr.clients().unwrap();
+        assert_eq!(server.join().unwrap(), ["j/clients"]);
+        assert_eq!(clients.len(), 1);
+        assert_eq!(clients[0].address, 0x55d0c3a0);
+        assert_eq!(clients[0].workspace.id, -98);
+        assert_eq!(clients[0].workspace.name, "special:scratch");
+        assert_eq!(clients[0].fullscreen, 0);
+    }
+
+    #[test]
+    fn no_active_window() {
+        let (hypr, server) = replay(&["{}"]);
+        assert!(hypr.active_window().unwrap().is_none());
+        assert_eq!(server.join().unwrap(), ["j/activewindow"]);
+    }
+
+    #[test]
+    fn dispatch_replies() {
+        let (hypr, server) = replay(&["ok", "Invalid dispatcher"]);
+        assert!(hypr.dispatch("focuswindow address:0x1").is_ok());
+        match hypr.dispatch("nosuchdispatcher") {
+            Err(WintError::Hyprland(reply)) => assert_eq!(reply, "Invalid dispatcher"),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(
+            server.join().unwrap(),
+            [
+                "dispatch focuswindow address:0x1",
+                "dispatch nosuchdispatcher"
+            ]
+        );
+    }
+
+    #[test]
+    fn batch_framing() {
+        let (hypr, server) = replay(&["ok\n\nok\n\n", "ok\n\nInvalid dispatcher\n\n"]);
+        let cmds = [
+            String::from("focuswindow address:0x1"),
+            String::from("alterzorder top,address:0x1"),
+        ];
+        assert!(hypr.dispatch_batch(&cmds).is_ok());
+        assert!(hypr.dispatch_batch(&cmds).is_err());
+        // nothing is sent for an empty batch
+        assert!(hypr.dispatch_batch(&[]).is_ok());
+        let batch =
+            "[[BATCH]]dispatch focuswindow address:0x1;dispatch alterzorder top,address:0x1";
+        assert_eq!(server.join().unwrap(), [batch, batch]);
+    }
+}
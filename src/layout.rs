@@ -0,0 +1,291 @@
+//! `<layout name="...">` in tilings.xml: several windows tiled by one command.
+//! Each `<slot>` gives a class and/or a title regex and a geometry, and the windows of
+//! the current workspace go to the slots they match:
+//!
+//! ```xml
+//! <display resolution="*">
+//!   <layout name="writing">
+//!     <slot class="emacs" geometry="0,0,60%,100%"/>
+//!     <slot class="org.pwmt.zathura" geometry="60%,0,40%,100%"/>
+//!   </layout>
+//! </display>
+//! ```
+use crate::script::WindowMatch;
+use crate::tiling::{
+    place, Decorations, Display, Displays, Float, Placement, Tile, ANY_RESOLUTION,
+};
+use crate::{Client, Monitor, WintError};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Slot {
+    #[serde(rename = "@class", default)]
+    pub class: Option<String>,
+    /// A regex matching the title
+    #[serde(rename = "@title", default)]
+    pub title: Option<String>,
+    #[serde(rename = "@geometry", default)]
+    pub geometry: String,
+    #[serde(rename = "@float", default)]
+    pub float: Option<Float>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct Layout {
+    #[serde(rename = "@name", default)]
+    pub name: String,
+    /// The default for the slots which do not set their own `float`
+    #[serde(rename = "@float", default)]
+    pub float: Option<Float>,
+    #[serde(rename = "slot", default)]
+    pub slots: Vec<Slot>,
+}
+
+impl Layout {
+    pub fn matchers(&self) -> Result<Vec<WindowMatch>, WintError> {
+        self.slots
+            .iter()
+            .map(|slot| WindowMatch::new(slot.class.as_deref(), slot.title.as_deref()))
+            .collect()
+    }
+}
+
+/// Look up the layout for the given resolution, falling back to `<display resolution="*">`
+pub fn find_layout<'a>(
+    tilings: &'a Displays,
+    name: &str,
+    resolution: &str,
+) -> Option<(&'a Display, &'a Layout)> {
+    let find = |resolution: &str| {
+        tilings
+            .items
+            .iter()
+            .filter(|disp| disp.resolution == resolution)
+            .flat_map(|disp| disp.layouts().map(move |l| (disp, l)))
+            .find(|(_, l)| l.name == name)
+    };
+    find(resolution).or_else(|| find(ANY_RESOLUTION))
+}
+
+/// The monitor with the focus, where the current workspace is
+pub fn focused_monitor(monitors: &[Monitor]) -> &Monitor {
+    monitors.iter().find(|m| m.focused).unwrap_or(&monitors[0])
+}
+
+/// Which window goes to each slot. A slot matched by a single window, once the windows
+/// of the other slots are set aside, takes it; when several windows are left for a slot,
+/// `choose` picks one of them. Slots matched by no window stay empty.
+pub fn fill_slots<'a, E, F>(
+    matchers: &[WindowMatch],
+    clients: &[&'a Client],
+    mut choose: F,
+) -> Result<Vec<Option<&'a Client>>, E>
+where
+    F: FnMut(&WindowMatch, &[&'a Client]) -> Result<&'a Client, E>,
+{
+    let mut filled: Vec<Option<&'a Client>> = vec![None; matchers.len()];
+    let candidates = |filled: &[Option<&'a Client>], matcher: &WindowMatch| {
+        clients
+            .iter()
+            .filter(|c| matcher.matches(c))
+            .filter(|c| !filled.iter().flatten().any(|f| f.address == c.address))
+            .cloned()
+            .collect::<Vec<&'a Client>>()
+    };
+    loop {
+        let mut progress = true;
+        while progress {
+            progress = false;
+            for i in 0..matchers.len() {
+                if filled[i].is_none() {
+                    if let [only] = candidates(&filled, &matchers[i])[..] {
+                        filled[i] = Some(only);
+                        progress = true;
+                    }
+                }
+            }
+        }
+        // each choice can leave a single window for the other slots
+        let open = (0..matchers.len())
+            .filter(|&i| filled[i].is_none())
+            .map(|i| (i, candidates(&filled, &matchers[i])))
+            .find(|(_, left)| left.len() > 1);
+        match open {
+            Some((i, left)) => filled[i] = Some(choose(&matchers[i], &left)?),
+            None => return Ok(filled),
+        }
+    }
+}
+
+/// Where the windows of the filled slots go on the monitor, in the order of the slots
+pub fn place_slots(
+    tilings: &Displays,
+    display: &Display,
+    layout: &Layout,
+    filled: &[Option<&Client>],
+    monitor: &Monitor,
+    decorations: &Decorations,
+) -> Result<Vec<Placement>, WintError> {
+    layout
+        .slots
+        .iter()
+        .zip(filled)
+        .filter_map(|(slot, client)| client.map(|c| (slot, c)))
+        .map(|(slot, client)| {
+            let tile = Tile {
+                geometry: slot
+                    .geometry
+                    .parse()
+                    .map_err(|e| WintError::Tilings(format!("layout {}: {}", layout.name, e)))?,
+                area: display.area.or(tilings.area).unwrap_or_default(),
+                float: slot
+                    .float
+                    .or(layout.float)
+                    .or(display.float)
+                    .or(tilings.float)
+                    .unwrap_or_default(),
+            };
+            Ok(Placement {
+                window: client.address,
+                rect: Some(place(&tile, monitor, decorations)),
+                float: tile.float,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::client;
+
+    const TILINGS: &str = r#"<displays float="toggle">
+        <display resolution="1920x1080">
+            <layout name="writing">
+                <slot class="emacs" geometry="0,0,50%,100%"/>
+            </layout>
+        </display>
+        <display resolution="*" area="workarea" float="no">
+            <layout name="writing" float="set">
+                <slot class="emacs" geometry="0,0,60%,100%" float="no"/>
+                <slot class="org.pwmt.zathura" geometry="60%,0,40%,100%"/>
+            </layout>
+            <layout name="reading">
+                <slot class="org.pwmt.zathura" geometry="0,0,100%,100%"/>
+            </layout>
+        </display>
+    </displays>"#;
+
+    fn matchers(classes: &[&str]) -> Vec<WindowMatch> {
+        classes
+            .iter()
+            .map(|c| WindowMatch::new(Some(c), None).unwrap())
+            .collect()
+    }
+
+    fn addresses(filled: &[Option<&Client>]) -> Vec<Option<u64>> {
+        filled.iter().map(|c| c.map(|c| c.address)).collect()
+    }
+
+    #[test]
+    fn single_windows_fill_their_slots() {
+        let clients = [client(1, "emacs", ""), client(2, "org.pwmt.zathura", "")];
+        let refs = clients.iter().collect::<Vec<&Client>>();
+        let filled = fill_slots(
+            &matchers(&["org.pwmt.zathura", "kitty", "emacs"]),
+            &refs,
+            |_, _| Err(()),
+        )
+        .unwrap();
+        assert_eq!(addresses(&filled), [Some(2), None, Some(1)]);
+    }
+
+    #[test]
+    fn a_choice_leaves_the_last_window_to_the_next_slot() {
+        let clients = [
+            client(1, "emacs", ""),
+            client(2, "emacs", ""),
+            client(3, "org.pwmt.zathura", ""),
+        ];
+        let refs = clients.iter().collect::<Vec<&Client>>();
+        let mut asked = Vec::new();
+        let filled = fill_slots(
+            &matchers(&["emacs", "emacs", "org.pwmt.zathura"]),
+            &refs,
+            |_, left| {
+                asked.push(left.iter().map(|c| c.address).collect::<Vec<u64>>());
+                Ok::<&Client, ()>(left[1])
+            },
+        )
+        .unwrap();
+        assert_eq!(asked, [vec![1, 2]]);
+        assert_eq!(addresses(&filled), [Some(2), Some(1), Some(3)]);
+    }
+
+    #[test]
+    fn layout_falls_back_to_any_resolution() {
+        let tilings: Displays = serde_xml_rs::from_str(TILINGS).unwrap();
+        let (display, layout) = find_layout(&tilings, "writing", "1920x1080").unwrap();
+        assert_eq!(
+            (display.resolution.as_str(), layout.slots.len()),
+            ("1920x1080", 1)
+        );
+        let (display, layout) = find_layout(&tilings, "reading", "1920x1080").unwrap();
+        assert_eq!(
+            (display.resolution.as_str(), layout.name.as_str()),
+            ("*", "reading")
+        );
+        let (display, _) = find_layout(&tilings, "writing", "1600x900").unwrap();
+        assert_eq!(display.resolution, "*");
+        assert!(find_layout(&tilings, "coding", "1920x1080").is_none());
+    }
+
+    #[test]
+    fn slots_inherit_float_and_area() {
+        let tilings: Displays = serde_xml_rs::from_str(TILINGS).unwrap();
+        let (display, layout) = find_layout(&tilings, "writing", "1600x900").unwrap();
+        let (emacs, zathura) = (client(1, "emacs", ""), client(2, "org.pwmt.zathura", ""));
+        let monitor = Monitor {
+            width: 1000,
+            height: 500,
+            scale: 1.0,
+            reserved: [0, 20, 0, 0],
+            ..Default::default()
+        };
+        let placements = place_slots(
+            &tilings,
+            display,
+            layout,
+            &[Some(&emacs), Some(&zathura)],
+            &monitor,
+            &Decorations::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            placements,
+            [
+                Placement {
+                    window: 1,
+                    rect: Some([0, 20, 600, 480]),
+                    float: Float::No,
+                },
+                Placement {
+                    window: 2,
+                    rect: Some([600, 20, 400, 480]),
+                    float: Float::Set,
+                },
+            ]
+        );
+        let (display, layout) = find_layout(&tilings, "writing", "1920x1080").unwrap();
+        let placements = place_slots(
+            &tilings,
+            display,
+            layout,
+            &[Some(&emacs)],
+            &monitor,
+            &Decorations::default(),
+        )
+        .unwrap();
+        assert_eq!(placements[0].float, Float::Toggle);
+        assert_eq!(placements[0].rect, Some([0, 0, 500, 500]));
+    }
+}
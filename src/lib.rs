@@ -4,20 +4,54 @@ extern crate serde;
 extern crate serde_xml_rs;
 use dirs::home_dir;
 use gtk::prelude::*;
-use serde_json::Value;
 use std::collections::HashMap;
 use std::fs::File;
 use std::path::{Path, PathBuf};
-use std::process::Command;
 use std::rc::Rc;
-use std::thread;
 use std::time::Duration;
 
+pub mod backend;
+pub mod command;
+pub mod daemon;
+pub mod fuzzy;
+pub mod hints;
+pub mod history;
+pub mod hyprland;
+pub mod layout;
+pub mod list;
+pub mod menu;
+pub mod save;
+pub mod script;
+pub mod state;
+#[cfg(test)]
+mod testing;
+pub mod tiling;
+pub use backend::{Backend, Dispatch, MockBackend, Retry};
+use hints::{HintStore, HintsConfig};
+use history::History;
+pub use hyprland::{monitor_of, Client, Hyprland, Monitor, Workspace};
+use state::Stored;
+
 #[derive(Debug)]
 pub enum WintError {
     //Errors from external libs:
     SerDe(serde_xml_rs::Error),
     NoConfigFile(std::io::Error),
+    Json(serde_json::Error),
+    Socket(std::io::Error),
+    Daemon(std::io::Error),
+    State(PathBuf, std::io::Error),
+    Regex(regex::Error),
+    Menu(String, std::io::Error),
+    //Errors of the command line:
+    NoWindow(String),
+    NoNick(String, String),
+    NoLayout(String, String),
+    Tilings(String),
+    //Errors reported by the window manager:
+    Hyprland(String),
+    NoHyprland,
+    NotApplied(String),
 }
 
 impl std::fmt::Display for WintError {
@@ -25,15 +59,53 @@ impl std::fmt::Display for WintError {
         match *self {
             WintError::SerDe(ref err) => err.fmt(f),
             WintError::NoConfigFile(ref err) => err.fmt(f),
+            WintError::Json(ref err) => err.fmt(f),
+            WintError::Socket(ref err) => write!(f, "Hyprland socket: {}", err),
+            WintError::Daemon(ref err) => write!(f, "winterd: {}", err),
+            WintError::State(ref path, ref err) => {
+                write!(f, "could not write {}: {}", path.display(), err)
+            }
+            WintError::Regex(ref err) => err.fmt(f),
+            WintError::Menu(ref command, ref err) => {
+                write!(f, "could not run {}: {}", command, err)
+            }
+            WintError::NoWindow(ref what) => write!(f, "no window matches {}", what),
+            WintError::NoNick(ref nick, ref resolution) => {
+                write!(f, "no nick {} for screen resolution {}", nick, resolution)
+            }
+            WintError::NoLayout(ref name, ref resolution) => {
+                write!(f, "no layout {} for screen resolution {}", name, resolution)
+            }
+            WintError::Tilings(ref what) => write!(f, "tilings.xml: {}", what),
+            WintError::Hyprland(ref reply) => write!(f, "Hyprland replied: {}", reply.trim()),
+            WintError::NoHyprland => write!(f, "HYPRLAND_INSTANCE_SIGNATURE is not set"),
+            WintError::NotApplied(ref what) => write!(f, "window manager did not {}", what),
         }
     }
 }
 impl std::error::Error for WintError {}
+impl WintError {
+    /// The exit status of `winj focus` and `wint apply`
+    pub fn exit_code(&self) -> i32 {
+        match *self {
+            WintError::NoWindow(_) => 2,
+            WintError::NoNick(_, _) | WintError::NoLayout(_, _) => 3,
+            WintError::NotApplied(_) => 4,
+            WintError::Socket(_) | WintError::Hyprland(_) | WintError::NoHyprland => 5,
+            _ => 1,
+        }
+    }
+}
 impl std::convert::From<serde_xml_rs::Error> for WintError {
     fn from(err: serde_xml_rs::Error) -> WintError {
         WintError::SerDe(err)
     }
 }
+impl std::convert::From<serde_json::Error> for WintError {
+    fn from(err: serde_json::Error) -> WintError {
+        WintError::Json(err)
+    }
+}
 impl std::convert::From<std::io::Error> for WintError {
     fn from(err: std::io::Error) -> WintError {
         WintError::NoConfigFile(err)
@@ -50,13 +122,6 @@ pub struct BlacklistedItems {
     pub item: Vec<BlacklistedItem>,
 }
 
-#[derive(Debug)]
-pub struct Monitor {
-    pub width: u32,
-    pub height: u32,
-    pub scale: f32,
-}
-
 #[derive(Debug, Deserialize, PartialEq)]
 pub enum TMPFile {
     #[serde(rename = "in_xdg_runtime")]
@@ -74,129 +139,100 @@ pub struct Config {
     #[serde(rename = "spaceBetweenButtons", default)]
     pub space_between_buttons: i32,
     pub maxwidth: usize,
+    /// Milliseconds to wait after each request to the window manager
+    #[serde(default = "default_delay")]
+    pub delay: u64,
+    /// How many times to send a request before reporting a failure
+    #[serde(default = "default_attempts")]
+    pub attempts: u32,
     pub blacklist: BlacklistedItems,
+    #[serde(default)]
+    pub hints: HintsConfig,
+    /// Start winj in search mode
+    #[serde(default)]
+    pub search: bool,
+    /// How many windows winj remembers
+    #[serde(default = "default_history")]
+    pub history: usize,
+    #[serde(default)]
+    pub actions: ActionKeys,
+    /// A dmenu-compatible launcher to show the windows with, instead of winj's own window
+    #[serde(default)]
+    pub menu: Option<String>,
+}
+
+fn default_delay() -> u64 {
+    100
+}
+fn default_attempts() -> u32 {
+    5
+}
+fn default_history() -> usize {
+    50
+}
+
+impl Config {
+    pub fn retry(&self) -> Retry {
+        Retry {
+            delay: Duration::from_millis(self.delay),
+            attempts: self.attempts,
+        }
+    }
+}
+
+/// The file where winj keeps its history of windows
+pub fn tmpfile_path(tmpfile: &TMPFile) -> PathBuf {
+    match *tmpfile {
+        TMPFile::Custom(ref x) => PathBuf::from(x),
+        TMPFile::InXdgRuntime => match std::env::var("XDG_RUNTIME_DIR") {
+            Ok(x) => Path::new(&x).join("winterreise"),
+            Err(_) => panic!("system does not have XDG_RUNTIME_DIR; please use custom <tmpfile><custom>...</custom></tmpfile> or <tmpfile><in_tmp/></custom> option in config"),
+        },
+        TMPFile::InTmp => PathBuf::from("/tmp/winterreise"),
+    }
+}
+
+/// The file where sticky hints are kept, next to the tmpfile
+pub fn hint_store_path(tmpfile: &TMPFile) -> PathBuf {
+    let mut p = tmpfile_path(tmpfile).into_os_string();
+    p.push("-hints");
+    PathBuf::from(p)
+}
+
+/// The focus history from winterd if it is running, otherwise the one kept by winj
+pub fn get_history(conf: &Config) -> History {
+    match daemon::history(&daemon::socket_path(&conf.tmpfile), conf.history) {
+        Ok(history) => history,
+        Err(_) => History::load(&tmpfile_path(&conf.tmpfile), conf.history),
+    }
 }
 
 pub struct WM {
-    pub wins: Rc<Vec<(u32, u32, String, String)>>,
-    pub desktop: u32,
+    pub wins: Rc<Vec<Client>>,
+    pub desktop: i64,
 }
 
 pub type Window = u64;
-fn parse_hex_to_u64(hex_str: &str) -> Result<u64, std::num::ParseIntError> {
-    // Ensure the string starts with "0x" and strip it
-    let trimmed = hex_str.trim_start_matches("0x");
-    // Parse the remaining part as a hexadecimal number
-    u64::from_str_radix(trimmed, 16)
-}
-pub fn get_wm_data() -> (
-    Rc<Vec<(Window, u32, String, String)>>,
-    Rc<Monitor>,
-    u32,
-    Window,
-) {
-    // Run the "hyprctl -j monitors" command
-    let output = Command::new("hyprctl")
-        .arg("-j")
-        .arg("monitors")
-        .output()
-        .expect("Failed to run hyprctl command");
-
-    // Convert output to string
-    let json_str = String::from_utf8(output.stdout).expect("Failed to parse output as UTF-8");
-
-    // Parse the JSON
-    let monitors: Value = serde_json::from_str(&json_str).expect("Failed to parse JSON output");
-    let geom = match monitors {
-        Value::Array(ref arr) => {
-            let monitor = arr.get(0).expect("Expected at least one monitor");
-            let width = monitor["width"].as_u64().unwrap() as u32;
-            let height = monitor["height"].as_u64().unwrap() as u32;
-            let scale = monitor["scale"].as_f64().unwrap() as f32;
-            let monitor = Monitor {
-                width,
-                height,
-                scale,
-            };
-            monitor
-        }
-        _ => panic!("Unexpected JSON format"),
-    };
+pub fn get_wm_data(backend: &dyn Backend) -> (Rc<Vec<Client>>, Rc<Vec<Monitor>>, i64, Window) {
+    let monitors = backend.monitors().expect("Failed to query monitors");
+    if monitors.is_empty() {
+        panic!("Expected at least one monitor");
+    }
 
-    // Run the "hyprctl -j clients" command
-    let output = Command::new("hyprctl")
-        .arg("-j")
-        .arg("clients")
-        .output()
-        .expect("Failed to run hyprctl command");
-
-    // Convert output to string
-    let json_str = String::from_utf8(output.stdout).expect("Failed to parse output as UTF-8");
-
-    // Parse the JSON
-    let clients: Value = serde_json::from_str(&json_str).expect("Failed to parse JSON output");
-    // Extract wins (address, workspace.id, title, class)
-    let wins = clients
-        .as_array()
-        .expect("Expected JSON array")
-        .iter()
-        .filter_map(|client| {
-            println!("Client: {:?}", client);
-            let address = parse_hex_to_u64(client["address"].as_str()?).unwrap();
-            let workspace_id = client["workspace"]["id"].as_u64()? as u32;
-            let title = client["title"].as_str()?.to_string();
-            let class = client["class"].as_str()?.to_string();
-            Some((address, workspace_id, title, class))
-        })
-        .collect::<Vec<_>>();
-
-    let output = Command::new("hyprctl")
-        .arg("-j")
-        .arg("activeworkspace")
-        .output();
-
-    let cur_desktop = if let Ok(output) = output {
-        if let Ok(json_str) = String::from_utf8(output.stdout) {
-            if let Ok(workspace) = serde_json::from_str::<Value>(&json_str) {
-                if let Some(id) = workspace["id"].as_u64() {
-                    id as u32
-                } else {
-                    0
-                }
-            } else {
-                0
-            }
-        } else {
-            0
-        }
-    } else {
-        0
+    // Special workspaces have negative ids, and are listed too
+    let wins = backend.clients().expect("Failed to query clients");
+
+    let cur_desktop = match backend.active_workspace() {
+        Ok(workspace) => workspace.id,
+        Err(_) => 0,
     };
 
-    let output = Command::new("hyprctl")
-        .arg("-j")
-        .arg("activewindow")
-        .output();
-
-    let cur_window = if let Ok(output) = output {
-        if let Ok(json_str) = String::from_utf8(output.stdout) {
-            if let Ok(window) = serde_json::from_str::<Value>(&json_str) {
-                if let Some(address) = window["address"].as_str() {
-                    parse_hex_to_u64(address).unwrap_or(0)
-                } else {
-                    0
-                }
-            } else {
-                0
-            }
-        } else {
-            0
-        }
-    } else {
-        0
+    let cur_window = match backend.active_window() {
+        Ok(Some(window)) => window.address,
+        _ => 0,
     };
 
-    (Rc::new(wins), Rc::new(geom), cur_desktop, cur_window)
+    (Rc::new(wins), Rc::new(monitors), cur_desktop, cur_window)
 }
 
 pub fn abbreviate(x: String, maxlen: usize) -> String {
@@ -218,78 +254,158 @@ pub fn abbreviate(x: String, maxlen: usize) -> String {
         );
     }
 }
-pub fn make_vbox(
-    wins: &Rc<Vec<(Window, u32, String, String)>>,
-    desktop: Option<u32>,
-    space_between_buttons: i32,
-    maxlen: usize,
-    blacklist: &Rc<BlacklistedItems>,
-    active: &Window,
-) -> (gtk::Box, HashMap<u8, Window>) {
-    let vbox = gtk::Box::new(gtk::Orientation::Vertical, space_between_buttons);
-    vbox.style_context().add_class("main_vbox");
-    let mut charhints: HashMap<u8, Window> = HashMap::new();
-    let mut j = 0 as u8;
-    match desktop {
-        Some(d) => println!("only showing windows on desktop {}", d),
-        None => println!("showing windows on all desktops"),
-    }
-    for (num, win_desktop, name, class) in (*wins)
-        .iter()
+
+/// The windows to list: those on the desktop (if given) whose class is not blacklisted
+pub fn visible_clients<'a>(
+    wins: &'a [Client],
+    desktop: Option<i64>,
+    blacklist: &BlacklistedItems,
+) -> Vec<&'a Client> {
+    wins.iter()
         .filter(|win| match desktop {
-            Some(d) => d == win.1,
+            Some(d) => d == win.workspace.id,
             None => true,
         })
         .filter(|win| {
-            !(*blacklist)
+            !blacklist
                 .item
                 .iter()
                 .map(|i| &i.class)
                 .collect::<Vec<&String>>()
-                .contains(&&win.3)
+                .contains(&&win.class)
         })
-    {
-        let class_sanitized = class.replace(".", "_");
+        .collect()
+}
+
+/// The windows to show, with their hints. In sticky mode, the hints kept at `store_path`
+/// are read and updated under its lock, so that two winj at once cannot lose each other's hints.
+pub fn hinted_clients<'a>(
+    wins: &'a [Client],
+    desktop: Option<i64>,
+    blacklist: &BlacklistedItems,
+    hints_conf: &HintsConfig,
+    store_path: Option<&Path>,
+    history: &History,
+) -> Vec<(String, &'a Client)> {
+    let visible = visible_clients(wins, desktop, blacklist);
+    let path = match store_path {
+        Some(p) => p,
+        None => return hints_conf.assign(visible, None, history),
+    };
+    let mut hinted = None;
+    let updated = HintStore::update(path, |store| {
+        store.prune(wins);
+        hinted = Some(hints_conf.assign(visible.clone(), Some(store), history));
+    });
+    if let Err(e) = updated {
+        eprintln!("ERROR: could not save sticky hints: {}", e);
+    }
+    hinted.unwrap_or_else(|| hints_conf.assign(visible, None, history))
+}
+
+/// The style class of the buttons of a window: `wbtn_` and the class, with dots replaced
+pub fn css_class(class: &str) -> String {
+    ["wbtn_", &class.replace(".", "_")].concat()
+}
+
+pub fn make_vbox(
+    hinted: &[(String, &Client)],
+    space_between_buttons: i32,
+    maxlen: usize,
+    active: &Window,
+) -> (gtk::Box, HashMap<String, Client>, Vec<(String, gtk::Box)>) {
+    let vbox = gtk::Box::new(gtk::Orientation::Vertical, space_between_buttons);
+    vbox.style_context().add_class("main_vbox");
+    let mut charhints: HashMap<String, Client> = HashMap::new();
+    let mut rows: Vec<(String, gtk::Box)> = Vec::new();
+    for (hint, client) in hinted {
+        let class_style = css_class(&client.class);
         let hbox = gtk::Box::new(gtk::Orientation::Horizontal, space_between_buttons);
         let lbtn = gtk::Button::new();
-        let llbl = gtk::Label::new(Some(&format!("{}", (j + 97) as char)));
-        if num == active {
+        let llbl = gtk::Label::new(Some(hint));
+        if client.address == *active {
             lbtn.style_context().add_class("wmjump_lbtn_current");
         } else {
-            lbtn.style_context()
-                .add_class(&["wbtn_", &class_sanitized].concat()[..]);
+            lbtn.style_context().add_class(&class_style);
             lbtn.style_context().add_class("wmjump_lbtn");
         }
         lbtn.add(&llbl);
         let rbtn = gtk::Button::new();
-        let rlbl = gtk::Label::new(Some(&format!("{}", (j + 97) as char)));
-        if num == active {
+        let rlbl = gtk::Label::new(Some(hint));
+        if client.address == *active {
             rbtn.style_context().add_class("wmjump_rbtn_current");
         } else {
-            rbtn.style_context()
-                .add_class(&["wbtn_", &class_sanitized].concat()[..]);
+            rbtn.style_context().add_class(&class_style);
             rbtn.style_context().add_class("wmjump_rbtn");
         }
         rbtn.add(&rlbl);
         let btn = gtk::Button::new();
-        let truncated = name.clone();
+        let truncated = client.title.clone();
         let lbl = gtk::Label::new(Some(&format!(
             "{}: {}",
-            win_desktop,
+            client.workspace.name,
             abbreviate(truncated, maxlen)
         )));
-        btn.style_context()
-            .add_class(&["wbtn_", &class_sanitized].concat()[..]);
+        btn.style_context().add_class(&class_style);
         btn.style_context().add_class("wmjump_button");
         btn.add(&lbl);
         hbox.add(&lbtn);
         hbox.add(&btn);
         hbox.add(&rbtn);
         vbox.add(&hbox);
-        charhints.insert(j, *num);
-        j += 1;
+        charhints.insert(hint.clone(), (*client).clone());
+        rows.push((hint.clone(), hbox));
+    }
+    return (vbox, charhints, rows);
+}
+
+/// Dim the rows whose hints do not start with what has been typed so far
+pub fn dim_rows(rows: &[(String, gtk::Box)], typed: &str) {
+    for (hint, hbox) in rows {
+        if hint.starts_with(typed) {
+            hbox.style_context().remove_class("wmjump_row_dimmed");
+        } else {
+            hbox.style_context().add_class("wmjump_row_dimmed");
+        }
+    }
+}
+
+/// Show only the rows whose windows match the search, best match first,
+/// and return the best match
+pub fn filter_rows<'a>(
+    vbox: &gtk::Box,
+    rows: &[(String, gtk::Box)],
+    hints: &'a HashMap<String, Client>,
+    pattern: &str,
+) -> Option<&'a Client> {
+    let clients = rows
+        .iter()
+        .map(|(h, _)| &hints[h])
+        .collect::<Vec<&Client>>();
+    let ranked = fuzzy::rank(pattern, &clients);
+    for (hint, hbox) in rows {
+        hbox.set_visible(ranked.iter().any(|c| c.address == hints[hint].address));
+    }
+    for (i, client) in ranked.iter().enumerate() {
+        if let Some((_, hbox)) = rows
+            .iter()
+            .find(|(h, _)| hints[h].address == client.address)
+        {
+            vbox.reorder_child(hbox, i as i32);
+        }
+    }
+    ranked.first().copied()
+}
+
+/// Highlight the row of the window selected by walking the history
+pub fn select_row(rows: &[(String, gtk::Box)], hints: &HashMap<String, Client>, win: Window) {
+    for (hint, hbox) in rows {
+        if hints[hint].address == win {
+            hbox.style_context().add_class("wmjump_row_selected");
+        } else {
+            hbox.style_context().remove_class("wmjump_row_selected");
+        }
     }
-    return (vbox, charhints);
 }
 
 pub fn get_config_dir() -> PathBuf {
@@ -324,34 +440,128 @@ pub fn check_tilings(p: &Path) -> () {
     }
 }
 
-pub fn go_to_window(win: Window) {
-    println!("-- going to window {:x}\n   ...", win);
-    let jumper = Command::new("hyprctl")
-        .arg("dispatch")
-        .arg("focuswindow")
-        .arg(format!("address:0x{:x}", win))
-        .output()
-        .expect("Failed to run hyprctl command");
+/// What winj does with the window whose hint is typed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Jump,
+    Close,
+    Fullscreen,
+    Float,
+    Pin,
+}
 
-    if jumper.status.success() {
-        println!(
-            "Command succeeded: {}",
-            String::from_utf8_lossy(&jumper.stdout)
-        );
-    } else {
-        eprintln!(
-            "Command failed with status {}: {}",
-            jumper.status,
-            String::from_utf8_lossy(&jumper.stderr)
-        );
+impl Action {
+    /// The title of winj while the action waits for a hint
+    pub fn title(&self) -> &'static str {
+        match *self {
+            Action::Jump => "Jump to...",
+            Action::Close => "Close...",
+            Action::Fullscreen => "Fullscreen...",
+            Action::Float => "Float or tile...",
+            Action::Pin => "Pin or unpin...",
+        }
+    }
+}
+
+/// The `<actions>` section of config.xml: the keys which select an action before the hint
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct ActionKeys {
+    pub close: String,
+    pub fullscreen: String,
+    pub float: String,
+    pub pin: String,
+    /// Followed by a hint and a workspace, sends the window there
+    #[serde(rename = "move")]
+    pub move_to_workspace: String,
+}
+
+impl Default for ActionKeys {
+    fn default() -> ActionKeys {
+        ActionKeys {
+            close: String::from("X"),
+            fullscreen: String::from("F"),
+            float: String::from("T"),
+            pin: String::from("P"),
+            move_to_workspace: String::from("M"),
+        }
+    }
+}
+
+impl ActionKeys {
+    fn is(key: &str, c: char) -> bool {
+        key.starts_with(c)
+    }
+
+    pub fn action(&self, c: char) -> Option<Action> {
+        if ActionKeys::is(&self.close, c) {
+            Some(Action::Close)
+        } else if ActionKeys::is(&self.fullscreen, c) {
+            Some(Action::Fullscreen)
+        } else if ActionKeys::is(&self.float, c) {
+            Some(Action::Float)
+        } else if ActionKeys::is(&self.pin, c) {
+            Some(Action::Pin)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_move(&self, c: char) -> bool {
+        ActionKeys::is(&self.move_to_workspace, c)
+    }
+}
+
+/// Perform the action on the window, checking that the window manager complied
+pub fn act_on_window(
+    backend: &dyn Backend,
+    win: Window,
+    action: Action,
+    retry: &Retry,
+) -> Result<(), WintError> {
+    let before = backend.client(win)?;
+    let toggled = |what: &str, cmd: Dispatch, state: fn(&Client) -> bool| {
+        let was = before.as_ref().is_some_and(state);
+        retry.toggle(
+            &format!("{} window 0x{:x}", what, win),
+            || backend.execute(&cmd),
+            || Ok(backend.client(win)?.is_some_and(|c| state(&c) != was)),
+        )
+    };
+    match action {
+        Action::Jump => {
+            println!("-- going to window {:x}\n   ...", win);
+            retry.focus(backend, win)?;
+            backend.raise(win)
+        }
+        Action::Close => retry.run(
+            &format!("close window 0x{:x}", win),
+            || backend.execute(&Dispatch::Close(win)),
+            || Ok(backend.client(win)?.is_none()),
+        ),
+        Action::Fullscreen => {
+            // the dispatcher acts on the active window
+            retry.focus(backend, win)?;
+            toggled("fullscreen", Dispatch::Fullscreen, |c| c.fullscreen != 0)
+        }
+        Action::Float => toggled("float", Dispatch::ToggleFloating(win), |c| c.floating),
+        Action::Pin => toggled("pin", Dispatch::TogglePin(win), |c| c.pinned),
     }
+}
 
-    thread::sleep(Duration::from_millis(100));
+pub fn go_to_window(backend: &dyn Backend, win: Window, retry: &Retry) -> Result<(), WintError> {
+    act_on_window(backend, win, Action::Jump, retry)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let _ = Command::new("hyprctl")
-        .arg("dispatch")
-        .arg("alterzorder")
-        .arg(format!("top,address:0x{:x}", win))
-        .status()
-        .expect("Failed to raise window");
+    #[test]
+    fn default_config_parses() {
+        let conf: Config = serde_xml_rs::from_str(include_str!("config/config.xml")).unwrap();
+        assert_eq!(conf.menu, None);
+        assert_eq!(conf.actions.action('X'), Some(Action::Close));
+        assert_eq!(conf.hints.letters(), hints::default_alphabet());
+    }
 }
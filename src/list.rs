@@ -0,0 +1,98 @@
+//! `winj list`: the windows winj would show, with their hints, for jq, fzf or status bars
+use crate::{css_class, Client, WintError};
+
+/// A row of the list
+#[derive(Debug, Clone, Serialize, PartialEq)]
+pub struct Entry {
+    pub hint: String,
+    /// e.g. `0x55d0c3a0`, as accepted by Hyprland's `address:` selectors
+    pub address: String,
+    /// The name of the workspace, which is its number for ordinary workspaces
+    pub workspace: String,
+    pub class: String,
+    pub title: String,
+    /// The style class of the buttons of the window in `style.css`
+    pub css_class: String,
+}
+
+pub fn entries(hinted: &[(String, &Client)]) -> Vec<Entry> {
+    hinted
+        .iter()
+        .map(|(hint, client)| Entry {
+            hint: hint.clone(),
+            address: format!("{:#x}", client.address),
+            workspace: client.workspace.name.clone(),
+            class: client.class.clone(),
+            title: client.title.clone(),
+            css_class: css_class(&client.class),
+        })
+        .collect()
+}
+
+pub fn to_json(entries: &[Entry]) -> Result<String, WintError> {
+    Ok(serde_json::to_string_pretty(entries)?)
+}
+
+/// One line per window: hint, address, workspace, class, title and style class,
+/// separated by tabs; tabs and newlines in titles become spaces
+pub fn to_tsv(entries: &[Entry]) -> String {
+    let clean = |s: &str| s.replace(['\t', '\n', '\r'], " ");
+    entries
+        .iter()
+        .map(|e| {
+            format!(
+                "{}\t{}\t{}\t{}\t{}\t{}\n",
+                e.hint,
+                e.address,
+                clean(&e.workspace),
+                clean(&e.class),
+                clean(&e.title),
+                e.css_class
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::client;
+
+    #[test]
+    fn tsv_lines_are_cleaned() {
+        let mut c = client(0x55d0c3a0, "org.pwmt.zathura", "a\tb\nc\r");
+        c.workspace.name = String::from("special:scratch");
+        let entries = entries(&[(String::from("ab"), &c)]);
+        assert_eq!(entries[0].css_class, "wbtn_org_pwmt_zathura");
+        assert_eq!(
+            to_tsv(&entries),
+            "ab\t0x55d0c3a0\tspecial:scratch\torg.pwmt.zathura\ta b c \twbtn_org_pwmt_zathura\n"
+        );
+    }
+
+    #[test]
+    fn json_field_names() {
+        let c = client(1, "kitty", "htop");
+        let json = to_json(&entries(&[(String::from("a"), &c)])).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        let fields = value[0]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>();
+        assert_eq!(
+            fields,
+            [
+                "address",
+                "class",
+                "css_class",
+                "hint",
+                "title",
+                "workspace"
+            ]
+        );
+        assert_eq!(value[0]["address"], "0x1");
+        assert_eq!(value[0]["title"], "htop");
+    }
+}
@@ -0,0 +1,87 @@
+//! winj through a dmenu-compatible launcher (`dmenu`, `rofi -dmenu`, `fuzzel --dmenu`,
+//! `wofi --dmenu`...) instead of its own window: the windows are written to the standard
+//! input of the launcher, one per line starting with the hint, and the line it prints
+//! back is the window to go to.
+use crate::{abbreviate, Client, WintError};
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// The lines shown by the launcher: hint, workspace, title and class
+pub fn lines(hinted: &[(String, &Client)], maxlen: usize) -> String {
+    hinted
+        .iter()
+        .map(|(hint, client)| {
+            format!(
+                "{}  {}: {}  [{}]\n",
+                hint,
+                client.workspace.name,
+                abbreviate(client.title.replace('\n', " "), maxlen),
+                client.class
+            )
+        })
+        .collect()
+}
+
+/// The window of the chosen line; the hint alone is enough, so that it can be typed
+pub fn parse_choice<'a>(choice: &str, hinted: &[(String, &'a Client)]) -> Option<&'a Client> {
+    let hint = choice.split_whitespace().next()?;
+    hinted
+        .iter()
+        .find(|(h, _)| h == hint)
+        .map(|(_, client)| *client)
+}
+
+/// Run the launcher with `sh -c`, give it the lines, and return the line chosen;
+/// `None` when it was dismissed (it printed nothing or exited with an error status)
+pub fn choose(command: &str, lines: &str) -> Result<Option<String>, WintError> {
+    let mut child = Command::new("sh")
+        .arg("-c")
+        .arg(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(|e| WintError::Menu(command.to_string(), e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // a launcher may exit without reading everything, e.g. a stub picking the first line
+        let _ = stdin.write_all(lines.as_bytes());
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| WintError::Menu(command.to_string(), e))?;
+    let choice = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if !output.status.success() || choice.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(choice))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::client;
+
+    #[test]
+    fn the_chosen_line_is_the_window() {
+        let clients = [client(1, "kitty", "htop"), client(2, "firefox", "docs")];
+        let hinted = vec![
+            (String::from("a"), &clients[0]),
+            (String::from("b"), &clients[1]),
+        ];
+        let lines = lines(&hinted, 80);
+        let choice = choose("head -n 1", &lines).unwrap().unwrap();
+        assert!(choice.starts_with("a  "));
+        assert_eq!(parse_choice(&choice, &hinted).map(|c| c.address), Some(1));
+        assert_eq!(parse_choice("b", &hinted).map(|c| c.address), Some(2));
+        assert_eq!(parse_choice("z", &hinted).map(|c| c.address), None);
+        assert_eq!(parse_choice("", &hinted).map(|c| c.address), None);
+    }
+
+    #[test]
+    fn dismissed_menu() {
+        assert_eq!(choose("true", "a  1: htop  [kitty]\n").unwrap(), None);
+        assert_eq!(
+            choose("head -n 1; exit 1", "a  1: htop  [kitty]\n").unwrap(),
+            None
+        );
+    }
+}
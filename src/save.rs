@@ -0,0 +1,309 @@
+//! The save mode of wint: the current geometries of windows are written to tilings.xml
+//! as nicks, under the `<display>` of the resolution of their monitor. The file is
+//! edited as text, so that its comments and its layout are kept.
+use crate::state::write_atomic;
+use crate::tiling::{Area, Decorations, Displays, TILED_NICK};
+use crate::{monitor_of, Client, Monitor, WintError};
+use regex::Regex;
+use std::path::Path;
+
+/// A nick to write to tilings.xml
+#[derive(Debug, Clone, PartialEq)]
+pub struct SavedNick {
+    pub nick: String,
+    pub resolution: String,
+    pub geometry: String,
+}
+
+/// The characters which cannot be written in an attribute without escaping,
+/// and `-`, which gives the window back to the tiling layout
+pub fn valid_nick(nick: &str) -> bool {
+    nick != TILED_NICK && !nick.contains(['"', '\'', '<', '>', '&'])
+}
+
+/// The `area` of the display with this resolution, or of all displays
+pub fn area_for(tilings: &Displays, resolution: &str) -> Area {
+    tilings
+        .items
+        .iter()
+        .find(|disp| disp.resolution == resolution)
+        .and_then(|disp| disp.area)
+        .or(tilings.area)
+        .unwrap_or_default()
+}
+
+/// The `geometry` attribute which puts a window back where the client is, in physical
+/// pixels; the opposite of [`crate::tiling::place`]
+pub fn geometry_of(
+    client: &Client,
+    monitor: &Monitor,
+    area: Area,
+    decorations: &Decorations,
+) -> String {
+    let scale = monitor.scale;
+    let (mut x, mut y) = (monitor.x as f32, monitor.y as f32);
+    let mut border = 0.0;
+    if area == Area::WorkArea {
+        let [left, top, _right, _bottom] = monitor.reserved;
+        let [gap_top, _gap_right, _gap_bottom, gap_left] = decorations.gaps_out;
+        x += (left + gap_left) as f32;
+        y += (top + gap_top) as f32;
+        border = decorations.border_size as f32;
+    }
+    let px = |v: f32| (v * scale).round().max(0.0) as u32;
+    format!(
+        "{},{},{},{}",
+        px(client.at[0] as f32 - x - border),
+        px(client.at[1] as f32 - y - border),
+        px(client.size[0] as f32 + 2.0 * border),
+        px(client.size[1] as f32 + 2.0 * border)
+    )
+}
+
+/// The nick for the current geometry of the client, on the monitor showing it
+pub fn saved_nick(
+    tilings: &Displays,
+    nick: &str,
+    client: &Client,
+    monitors: &[Monitor],
+    decorations: &Decorations,
+) -> SavedNick {
+    let monitor = monitor_of(monitors, client).unwrap_or(&monitors[0]);
+    let resolution = monitor.resolution();
+    let area = area_for(tilings, &resolution);
+    SavedNick {
+        nick: nick.to_string(),
+        geometry: geometry_of(client, monitor, area, decorations),
+        resolution,
+    }
+}
+
+/// The text with the comments blanked out, so that commented elements are not found;
+/// the offsets are the same as in the text
+fn without_comments(xml: &str) -> String {
+    let mut masked = xml.as_bytes().to_vec();
+    let mut from = 0;
+    while let Some(start) = xml[from..].find("<!--").map(|i| from + i) {
+        let end = xml[start..]
+            .find("-->")
+            .map(|i| start + i + 3)
+            .unwrap_or(xml.len());
+        masked[start..end].iter_mut().for_each(|b| *b = b' ');
+        from = end;
+    }
+    String::from_utf8(masked).unwrap_or_default()
+}
+
+/// The whitespace at the start of the line containing `at`, if only whitespace is before it
+fn indent_before(xml: &str, at: usize) -> Option<&str> {
+    let line_start = xml[..at].rfind('\n').map(|i| i + 1).unwrap_or(0);
+    let before = &xml[line_start..at];
+    before.trim().is_empty().then_some(before)
+}
+
+fn attribute(name: &str, value: &str) -> String {
+    format!(r#"\b{}\s*=\s*["']{}["']"#, name, regex::escape(value))
+}
+
+/// Write the nick into the text of tilings.xml: its geometry is replaced if the display
+/// has it already, otherwise a `<window>` is added, and a `<display>` if needed
+pub fn insert_nick(xml: &str, saved: &SavedNick) -> String {
+    let masked = without_comments(xml);
+    let display = Regex::new(&format!(
+        r"<display\s[^>]*{}[^>]*>",
+        attribute("resolution", &saved.resolution)
+    ))
+    .unwrap();
+    let window = format!(
+        r#"<window nick="{}" geometry="{}"/>"#,
+        saved.nick, saved.geometry
+    );
+    let open = display
+        .find_iter(&masked)
+        .find(|m| !m.as_str().ends_with("/>"));
+    if let Some(open) = open {
+        let close = masked[open.end()..]
+            .find("</display>")
+            .map(|i| open.end() + i)
+            .unwrap_or(masked.len());
+        let existing = Regex::new(&format!(
+            r"<window\s[^>]*{}[^>]*>",
+            attribute("nick", &saved.nick)
+        ))
+        .unwrap()
+        .find(&masked[open.end()..close])
+        .map(|m| (open.end() + m.start(), open.end() + m.end()));
+        if let Some((start, end)) = existing {
+            let element = &xml[start..end];
+            let geometry = Regex::new(r#"\bgeometry\s*=\s*("[^"]*"|'[^']*')"#).unwrap();
+            let replaced = if geometry.is_match(element) {
+                geometry
+                    .replace(
+                        element,
+                        format!(r#"geometry="{}""#, saved.geometry).as_str(),
+                    )
+                    .to_string()
+            } else {
+                element.replacen(
+                    "<window",
+                    &format!(r#"<window geometry="{}""#, saved.geometry),
+                    1,
+                )
+            };
+            return [&xml[..start], &replaced, &xml[end..]].concat();
+        }
+        let display_indent = indent_before(xml, open.start()).unwrap_or("");
+        let last_window = masked[open.end()..close]
+            .rfind("<window")
+            .map(|i| open.end() + i);
+        let indent = last_window
+            .and_then(|w| indent_before(xml, w))
+            .map(String::from)
+            .unwrap_or(format!("{}  ", display_indent));
+        return match indent_before(xml, close) {
+            Some(before) => {
+                let line_start = close - before.len();
+                [
+                    &xml[..line_start],
+                    &format!("{}{}\n", indent, window),
+                    &xml[line_start..],
+                ]
+                .concat()
+            }
+            None => [&xml[..close], &window, &xml[close..]].concat(),
+        };
+    }
+    let new_display = |indent: &str| {
+        format!(
+            "{}<display resolution=\"{}\">\n{}  {}\n{}</display>\n",
+            indent, saved.resolution, indent, window, indent
+        )
+    };
+    match masked.rfind("</displays>") {
+        Some(close) => {
+            let indent = masked
+                .find("<display ")
+                .and_then(|d| indent_before(xml, d))
+                .unwrap_or("  ");
+            match indent_before(xml, close) {
+                Some(before) => {
+                    let line_start = close - before.len();
+                    [&xml[..line_start], &new_display(indent), &xml[line_start..]].concat()
+                }
+                None => [&xml[..close], "\n", &new_display(indent), &xml[close..]].concat(),
+            }
+        }
+        None => format!("{}<displays>\n{}</displays>\n", xml, new_display("  ")),
+    }
+}
+
+/// Write the nicks into tilings.xml
+pub fn save_nicks(xml_path: &Path, nicks: &[SavedNick]) -> Result<(), WintError> {
+    let mut xml = std::fs::read_to_string(xml_path)?;
+    for saved in nicks {
+        xml = insert_nick(&xml, saved);
+    }
+    // refuse to leave a file which wint could not read
+    serde_xml_rs::from_str::<Displays>(&xml)?;
+    write_atomic(xml_path, &xml)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::client;
+    use crate::tiling::{place, Float, Tile};
+
+    const XML: &str = r#"<displays>
+  <!-- <display resolution="1920x1080"><window nick="tex" geometry="1,1,1,1"/></display> -->
+  <display resolution="1920x1080">
+    <window nick="texpdf" geometry="0,0,10,10"/>
+    <window nick="tex" geometry="0,0,930,883"/>
+  </display>
+</displays>
+"#;
+
+    fn saved(nick: &str, resolution: &str, geometry: &str) -> SavedNick {
+        SavedNick {
+            nick: nick.to_string(),
+            resolution: resolution.to_string(),
+            geometry: geometry.to_string(),
+        }
+    }
+
+    #[test]
+    fn comments_are_blanked_in_place() {
+        let masked = without_comments("a<!-- b -->c<!-- d");
+        assert_eq!(masked, format!("a{}c{}", " ".repeat(10), " ".repeat(6)));
+    }
+
+    #[test]
+    fn geometry_is_replaced() {
+        let xml = insert_nick(XML, &saved("tex", "1920x1080", "0,0,960,1080"));
+        assert_eq!(
+            xml,
+            XML.replace(
+                r#"nick="tex" geometry="0,0,930,883""#,
+                r#"nick="tex" geometry="0,0,960,1080""#
+            )
+        );
+    }
+
+    #[test]
+    fn window_is_appended_to_its_display() {
+        let xml = insert_nick(XML, &saved("pdf", "1920x1080", "960,0,960,1080"));
+        assert_eq!(
+            xml,
+            XML.replace(
+                "  </display>",
+                "    <window nick=\"pdf\" geometry=\"960,0,960,1080\"/>\n  </display>"
+            )
+        );
+        serde_xml_rs::from_str::<Displays>(&xml).unwrap();
+    }
+
+    #[test]
+    fn display_is_added() {
+        let xml = insert_nick(XML, &saved("l", "1600x900", "0,0,800,900"));
+        assert_eq!(
+            xml,
+            XML.replace(
+                "</displays>",
+                "  <display resolution=\"1600x900\">\n    <window nick=\"l\" geometry=\"0,0,800,900\"/>\n  </display>\n</displays>"
+            )
+        );
+        let xml = insert_nick("", &saved("l", "1600x900", "0,0,800,900"));
+        let tilings = serde_xml_rs::from_str::<Displays>(&xml).unwrap();
+        assert_eq!(tilings.items[0].resolution, "1600x900");
+    }
+
+    #[test]
+    fn geometry_of_is_the_opposite_of_place() {
+        let monitor = Monitor {
+            width: 2560,
+            height: 1440,
+            scale: 2.0,
+            x: 1920,
+            reserved: [0, 30, 0, 0],
+            ..Default::default()
+        };
+        let decorations = Decorations {
+            gaps_out: [5, 10, 15, 20],
+            border_size: 2,
+        };
+        let geometry = "100,60,1200,800";
+        let tile = Tile {
+            geometry: geometry.parse().unwrap(),
+            area: Area::WorkArea,
+            float: Float::No,
+        };
+        let [x, y, width, height] = place(&tile, &monitor, &decorations);
+        let mut c = client(1, "", "");
+        c.at = [x, y];
+        c.size = [width, height];
+        assert_eq!(
+            geometry_of(&c, &monitor, Area::WorkArea, &decorations),
+            geometry
+        );
+    }
+}
@@ -0,0 +1,141 @@
+//! `winj focus` and `wint apply`: what the two programs do, for scripts and keybinds,
+//! without a window. The windows are given by class or title instead of by hint.
+use crate::backend::Retry;
+use crate::history::History;
+use crate::layout::{fill_slots, find_layout, focused_monitor, place_slots};
+use crate::tiling::{do_resize, load_tilings, place_client, Decorations, Placement};
+use crate::{
+    go_to_window, monitor_of, visible_clients, Backend, BlacklistedItems, Client, Window, WintError,
+};
+use regex::Regex;
+use std::fmt;
+use std::path::Path;
+
+/// Which windows a command is about: those of this class, whose title matches this regex
+#[derive(Debug, Clone)]
+pub struct WindowMatch {
+    pub class: Option<String>,
+    pub title: Option<Regex>,
+}
+
+impl WindowMatch {
+    pub fn new(class: Option<&str>, title: Option<&str>) -> Result<WindowMatch, WintError> {
+        Ok(WindowMatch {
+            class: class.map(String::from),
+            title: title
+                .map(Regex::new)
+                .transpose()
+                .map_err(WintError::Regex)?,
+        })
+    }
+
+    pub fn matches(&self, client: &Client) -> bool {
+        (self.class.is_some() || self.title.is_some())
+            && self.class.as_ref().is_none_or(|c| *c == client.class)
+            && self
+                .title
+                .as_ref()
+                .is_none_or(|r| r.is_match(&client.title))
+    }
+}
+
+impl fmt::Display for WindowMatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.class, &self.title) {
+            (Some(c), Some(t)) => write!(f, "class {} and title /{}/", c, t),
+            (Some(c), None) => write!(f, "class {}", c),
+            (None, Some(t)) => write!(f, "title /{}/", t),
+            (None, None) => write!(f, "nothing"),
+        }
+    }
+}
+
+/// The matching window which is not `taken`; when there are several, the one on
+/// `workspace`, then the most recently used
+pub fn find_window<'a>(
+    clients: &'a [Client],
+    matcher: &WindowMatch,
+    history: &History,
+    workspace: Option<i64>,
+    taken: &[Window],
+) -> Result<&'a Client, WintError> {
+    clients
+        .iter()
+        .filter(|c| matcher.matches(c) && !taken.contains(&c.address))
+        .min_by_key(|c| (Some(c.workspace.id) != workspace, history.rank(c.address)))
+        .ok_or_else(|| WintError::NoWindow(matcher.to_string()))
+}
+
+/// Jump to the matching window, and return it
+pub fn focus(
+    backend: &dyn Backend,
+    matcher: &WindowMatch,
+    history: &History,
+    retry: &Retry,
+) -> Result<Window, WintError> {
+    let clients = backend.clients()?;
+    let win = find_window(&clients, matcher, history, None, &[])?.address;
+    go_to_window(backend, win, retry)?;
+    Ok(win)
+}
+
+/// Give each window its nick, in one batch, like a `wint` command; a matcher picks
+/// a different window each time it is given, preferring the current workspace.
+/// The focus goes to the first window.
+pub fn apply(
+    backend: &dyn Backend,
+    xml_path: &Path,
+    assignments: &[(WindowMatch, String)],
+    decorations: &Decorations,
+    history: &History,
+    retry: &Retry,
+) -> Result<(), WintError> {
+    let clients = backend.clients()?;
+    let monitors = backend.monitors()?;
+    let workspace = backend.active_workspace()?.id;
+    let tilings = load_tilings(xml_path)?;
+    let mut layout: Vec<Placement> = Vec::new();
+    for (matcher, nick) in assignments {
+        let taken = layout.iter().map(|p| p.window).collect::<Vec<Window>>();
+        let client = find_window(&clients, matcher, history, Some(workspace), &taken)?;
+        match place_client(&tilings, nick, client, &monitors, decorations)? {
+            Some(placement) => layout.push(placement),
+            None => {
+                let monitor = monitor_of(&monitors, client).unwrap_or(&monitors[0]);
+                return Err(WintError::NoNick(nick.clone(), monitor.resolution()));
+            }
+        }
+    }
+    let focus = layout.first().map(|p| p.window);
+    do_resize(backend, &layout, focus, retry)
+}
+
+/// Tile the windows of the current workspace with the layout; when several windows
+/// match a slot, the most recently used one takes it. The focus goes to the first slot.
+pub fn apply_layout(
+    backend: &dyn Backend,
+    xml_path: &Path,
+    name: &str,
+    blacklist: &BlacklistedItems,
+    decorations: &Decorations,
+    history: &History,
+    retry: &Retry,
+) -> Result<(), WintError> {
+    let clients = backend.clients()?;
+    let monitors = backend.monitors()?;
+    let workspace = backend.active_workspace()?.id;
+    let tilings = load_tilings(xml_path)?;
+    let monitor = focused_monitor(&monitors);
+    let (display, layout) = find_layout(&tilings, name, &monitor.resolution())
+        .ok_or_else(|| WintError::NoLayout(name.to_string(), monitor.resolution()))?;
+    let windows = visible_clients(&clients, Some(workspace), blacklist);
+    let filled = fill_slots(&layout.matchers()?, &windows, |_, left| {
+        Ok::<&Client, WintError>(*left.iter().min_by_key(|c| history.rank(c.address)).unwrap())
+    })?;
+    let placements = place_slots(&tilings, display, layout, &filled, monitor, decorations)?;
+    if placements.is_empty() {
+        return Err(WintError::NoWindow(format!("a slot of layout {}", name)));
+    }
+    let focus = placements.first().map(|p| p.window);
+    do_resize(backend, &placements, focus, retry)
+}
@@ -0,0 +1,87 @@
+//! The files where winj keeps its state (the history and the sticky hints) can be
+//! written by several winj at once. A file is replaced by writing a temporary file
+//! next to it and renaming it over the old one, so readers never see half a file,
+//! and read-modify-write cycles hold a lock on a separate `.lock` file.
+use crate::{Client, Window, WintError};
+use std::ffi::OsString;
+use std::fs::File;
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let mut p = OsString::from(path.as_os_str());
+    p.push(suffix);
+    PathBuf::from(p)
+}
+
+/// Replace the contents of the file in one step
+pub fn write_atomic(path: &Path, contents: &str) -> Result<(), WintError> {
+    let tmp = sibling(path, &format!(".{}.tmp", std::process::id()));
+    let written = File::create(&tmp)
+        .and_then(|mut f| {
+            f.write_all(contents.as_bytes())?;
+            f.sync_all()
+        })
+        .and_then(|_| std::fs::rename(&tmp, path));
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(WintError::State(path.to_path_buf(), e));
+    }
+    Ok(())
+}
+
+/// Wait until no other process holds the lock of the file; the lock is released
+/// when the returned `File` is dropped
+pub fn lock(path: &Path) -> Result<File, WintError> {
+    let lock_path = sibling(path, ".lock");
+    let file = File::create(&lock_path).map_err(|e| WintError::State(lock_path.clone(), e))?;
+    // flock rather than File::lock, which needs a newer Rust than nixpkgs has
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(WintError::State(lock_path, std::io::Error::last_os_error()));
+    }
+    Ok(file)
+}
+
+/// What a state file holds: something about each of a set of windows
+pub trait Stored: Sized {
+    /// Add what the contents of the file hold
+    fn read(&mut self, contents: &str);
+
+    /// The contents of the file
+    fn contents(&self) -> String;
+
+    /// Keep only the windows for which `keep` is true
+    fn retain_windows(&mut self, keep: impl Fn(Window) -> bool);
+
+    /// Add what the file holds; a file which does not exist yet holds nothing
+    fn loaded(mut self, path: &Path) -> Self {
+        if let Ok(contents) = std::fs::read_to_string(path) {
+            self.read(&contents);
+        }
+        self
+    }
+
+    fn save(&self, path: &Path) -> Result<(), WintError> {
+        write_atomic(path, &self.contents())
+    }
+
+    /// Forget the windows which are gone
+    fn prune(&mut self, alive: &[Client]) {
+        self.retain_windows(|w| alive.iter().any(|c| c.address == w));
+    }
+}
+
+/// Read the file into `empty`, change it and write it back, all under the lock, so that
+/// the changes made meanwhile by another winj are not lost
+pub fn update<S, F>(path: &Path, empty: S, change: F) -> Result<S, WintError>
+where
+    S: Stored,
+    F: FnOnce(&mut S),
+{
+    let _lock = lock(path)?;
+    let mut state = empty.loaded(path);
+    change(&mut state);
+    state.save(path)?;
+    Ok(state)
+}
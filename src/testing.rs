@@ -0,0 +1,21 @@
+//! Helpers shared by the unit tests
+use crate::{Client, Retry, Window};
+use std::time::Duration;
+
+/// Retries which do not wait, so that the tests of failures are quick
+pub fn quick() -> Retry {
+    Retry {
+        delay: Duration::ZERO,
+        attempts: 3,
+    }
+}
+
+/// A window of this class, with this title
+pub fn client(address: Window, class: &str, title: &str) -> Client {
+    Client {
+        address,
+        class: class.to_string(),
+        title: title.to_string(),
+        ..Default::default()
+    }
+}
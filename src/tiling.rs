@@ -0,0 +1,580 @@
+use crate::backend::{close_to, Backend, Dispatch, Retry};
+use crate::layout::Layout;
+use crate::{monitor_of, Client, Monitor, Window, WintError};
+use std::cell::RefCell;
+use std::fs::File;
+use std::path::Path;
+use std::str::FromStr;
+
+#[derive(Debug, Deserialize)]
+pub struct WindowSimple {
+    #[serde(rename = "@nick", default)]
+    pub nick: String,
+    #[serde(rename = "@geometry", default)]
+    pub geometry: String,
+    #[serde(rename = "@float", default)]
+    pub float: Option<Float>,
+}
+
+/// What the geometries are relative to
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum Area {
+    /// The whole monitor
+    #[default]
+    #[serde(rename = "monitor")]
+    Monitor,
+    /// The monitor minus the space reserved by bars and `general:gaps_out`;
+    /// windows are shrunk by `general:border_size` to keep their borders inside
+    #[serde(rename = "workarea")]
+    WorkArea,
+}
+
+/// What to do with windows which Hyprland is tiling, since it ignores their geometry
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Default)]
+pub enum Float {
+    /// Leave them tiled
+    #[default]
+    #[serde(rename = "no")]
+    No,
+    /// Make them floating with `togglefloating`
+    #[serde(rename = "toggle")]
+    Toggle,
+    /// Make them floating with `setfloating`
+    #[serde(rename = "set")]
+    Set,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Display {
+    #[serde(rename = "@resolution", default)]
+    pub resolution: String,
+    #[serde(rename = "@area", default)]
+    pub area: Option<Area>,
+    #[serde(rename = "@float", default)]
+    pub float: Option<Float>,
+
+    /// The windows and layouts, in any order
+    #[serde(rename = "#content", default)]
+    pub items: Vec<DisplayItem>,
+}
+
+#[derive(Debug, Deserialize)]
+pub enum DisplayItem {
+    #[serde(rename = "window")]
+    Window(WindowSimple),
+    #[serde(rename = "layout")]
+    Layout(Layout),
+}
+
+impl Display {
+    pub fn windows(&self) -> impl Iterator<Item = &WindowSimple> {
+        self.items.iter().filter_map(|item| match item {
+            DisplayItem::Window(w) => Some(w),
+            _ => None,
+        })
+    }
+
+    pub fn layouts(&self) -> impl Iterator<Item = &Layout> {
+        self.items.iter().filter_map(|item| match item {
+            DisplayItem::Layout(l) => Some(l),
+            _ => None,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename = "displays", default)]
+pub struct Displays {
+    /// The default for the displays which do not set their own `area`
+    #[serde(rename = "@area", default)]
+    pub area: Option<Area>,
+    /// The default for the displays and windows which do not set their own `float`
+    #[serde(rename = "@float", default)]
+    pub float: Option<Float>,
+    #[serde(rename = "display", default)]
+    pub items: Vec<Display>,
+}
+
+/// Matches any resolution, used when there is no `display` for the monitor's resolution
+pub const ANY_RESOLUTION: &str = "*";
+
+/// Not looked up in tilings.xml: gives the window back to Hyprland's tiling layout
+pub const TILED_NICK: &str = "-";
+
+/// A coordinate or size in a `geometry` attribute
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Length {
+    /// Physical pixels, e.g. `800`
+    Pixels(u32),
+    /// Part of the monitor, e.g. `50%` or `0.5`
+    Fraction(f32),
+}
+
+impl FromStr for Length {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Length, String> {
+        let s = s.trim();
+        let bad = || format!("invalid length \"{}\"", s);
+        if let Some(percent) = s.strip_suffix('%') {
+            Ok(Length::Fraction(
+                percent.trim().parse::<f32>().map_err(|_| bad())? / 100.0,
+            ))
+        } else if s.contains('.') {
+            Ok(Length::Fraction(s.parse::<f32>().map_err(|_| bad())?))
+        } else {
+            Ok(Length::Pixels(s.parse::<u32>().map_err(|_| bad())?))
+        }
+    }
+}
+
+impl Length {
+    /// Physical pixels, given the full extent in physical pixels
+    pub fn resolve(&self, full: u32) -> u32 {
+        match *self {
+            Length::Pixels(px) => px,
+            Length::Fraction(f) => (f * full as f32).round() as u32,
+        }
+    }
+}
+
+/// The `geometry` attribute: `x,y,width,height`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Geometry {
+    pub x: Length,
+    pub y: Length,
+    pub width: Length,
+    pub height: Length,
+}
+
+impl FromStr for Geometry {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Geometry, String> {
+        let parts = s
+            .split(",")
+            .map(str::parse::<Length>)
+            .collect::<Result<Vec<Length>, String>>()?;
+        match parts[..] {
+            [x, y, width, height] => Ok(Geometry {
+                x,
+                y,
+                width,
+                height,
+            }),
+            _ => Err(format!("geometry \"{}\" should be x,y,width,height", s)),
+        }
+    }
+}
+
+impl Geometry {
+    /// `[x, y, width, height]` in physical pixels, fractions taken of the given area
+    pub fn resolve(&self, width: u32, height: u32) -> [u32; 4] {
+        [
+            self.x.resolve(width),
+            self.y.resolve(height),
+            self.width.resolve(width),
+            self.height.resolve(height),
+        ]
+    }
+}
+
+/// A nick looked up in tilings.xml
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tile {
+    pub geometry: Geometry,
+    pub area: Area,
+    pub float: Float,
+}
+
+fn find_nick<'a>(
+    tilings: &'a Displays,
+    nick: &str,
+    resolution: &str,
+) -> Option<(&'a Display, &'a WindowSimple)> {
+    tilings
+        .items
+        .iter()
+        .filter(|disp| disp.resolution == resolution)
+        .flat_map(|disp| disp.windows().map(move |w| (disp, w)))
+        .find(|(_, w)| w.nick == nick)
+}
+
+pub fn load_tilings(xml_path: &Path) -> Result<Displays, WintError> {
+    Ok(serde_xml_rs::from_reader(File::open(xml_path)?)?)
+}
+
+/// Look up the nick for the given resolution, falling back to `<display resolution="*">`;
+/// `None` when neither has it
+pub fn get_geometry(
+    tilings: &Displays,
+    nick: &str,
+    resolution: &str,
+) -> Result<Option<Tile>, WintError> {
+    find_nick(tilings, nick, resolution)
+        .or_else(|| find_nick(tilings, nick, ANY_RESOLUTION))
+        .map(|(disp, ni)| {
+            Ok(Tile {
+                geometry: ni
+                    .geometry
+                    .parse()
+                    .map_err(|e| WintError::Tilings(format!("nick {}: {}", nick, e)))?,
+                area: disp.area.or(tilings.area).unwrap_or_default(),
+                float: ni
+                    .float
+                    .or(disp.float)
+                    .or(tilings.float)
+                    .unwrap_or_default(),
+            })
+        })
+        .transpose()
+}
+
+/// Gaps and borders from Hyprland's `general` section
+#[derive(Debug, Clone, Default)]
+pub struct Decorations {
+    /// top, right, bottom, left
+    pub gaps_out: [i32; 4],
+    pub border_size: i32,
+}
+
+impl Decorations {
+    pub fn query(backend: &dyn Backend) -> Result<Decorations, WintError> {
+        Ok(Decorations {
+            gaps_out: backend.option("general:gaps_out")?.sides(),
+            border_size: backend.option("general:border_size")?.int.unwrap_or(0) as i32,
+        })
+    }
+}
+
+/// Where the tile goes on the monitor: `[x, y, width, height]` in layout coordinates
+pub fn place(tile: &Tile, monitor: &Monitor, decorations: &Decorations) -> [i32; 4] {
+    let scale = monitor.scale;
+    let (width, height) = monitor.size();
+    let (mut x, mut y) = (monitor.x as f32, monitor.y as f32);
+    let (mut w, mut h) = (width as f32 / scale, height as f32 / scale);
+    let mut border = 0.0;
+    if tile.area == Area::WorkArea {
+        let [left, top, right, bottom] = monitor.reserved;
+        let [gap_top, gap_right, gap_bottom, gap_left] = decorations.gaps_out;
+        x += (left + gap_left) as f32;
+        y += (top + gap_top) as f32;
+        w = (w - (left + gap_left + right + gap_right) as f32).max(0.0);
+        h = (h - (top + gap_top + bottom + gap_bottom) as f32).max(0.0);
+        border = decorations.border_size as f32;
+    }
+    let g = tile
+        .geometry
+        .resolve((w * scale).round() as u32, (h * scale).round() as u32);
+    [
+        (x + g[0] as f32 / scale + border).round() as i32,
+        (y + g[1] as f32 / scale + border).round() as i32,
+        (g[2] as f32 / scale - 2.0 * border).round() as i32,
+        (g[3] as f32 / scale - 2.0 * border).round() as i32,
+    ]
+}
+
+/// Where a window should go
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    pub window: Window,
+    /// `[x, y, width, height]` as computed by [`place`],
+    /// or `None` to give the window back to Hyprland's tiling layout
+    pub rect: Option<[i32; 4]>,
+    pub float: Float,
+}
+
+/// Where the nick puts the client, on the monitor showing it; `None` when the nick
+/// is not defined for the resolution of that monitor
+pub fn place_client(
+    tilings: &Displays,
+    nick: &str,
+    client: &Client,
+    monitors: &[Monitor],
+    decorations: &Decorations,
+) -> Result<Option<Placement>, WintError> {
+    if nick == TILED_NICK {
+        return Ok(Some(Placement {
+            window: client.address,
+            rect: None,
+            float: Float::No,
+        }));
+    }
+    let monitor = monitor_of(monitors, client).unwrap_or(&monitors[0]);
+    let tile = get_geometry(tilings, nick, &monitor.resolution())?;
+    Ok(tile.map(|tile| Placement {
+        window: client.address,
+        rect: Some(place(&tile, monitor, decorations)),
+        float: tile.float,
+    }))
+}
+
+/// Apply the placements in one batch which ends with the focus on `focus`;
+/// the batch is repeated for the windows which did not get into place
+pub fn do_resize(
+    backend: &dyn Backend,
+    layout: &[Placement],
+    focus: Option<Window>,
+    retry: &Retry,
+) -> Result<(), WintError> {
+    let clients = backend.clients()?;
+    let floating = |w: Window| clients.iter().any(|c| c.address == w && c.floating);
+    // decided once: a toggle sent again before Hyprland has applied the first one
+    // would give the window back to the tiling layout
+    let float = RefCell::new(
+        layout
+            .iter()
+            .filter_map(|p| match (p.rect, p.float) {
+                (None, _) if floating(p.window) => Some((p.window, Dispatch::SetTiled(p.window))),
+                (Some(_), Float::Toggle) if !floating(p.window) => {
+                    Some((p.window, Dispatch::ToggleFloating(p.window)))
+                }
+                (Some(_), Float::Set) if !floating(p.window) => {
+                    Some((p.window, Dispatch::SetFloating(p.window)))
+                }
+                _ => None,
+            })
+            .collect::<Vec<(Window, Dispatch)>>(),
+    );
+    let pending = RefCell::new(layout.iter().collect::<Vec<&Placement>>());
+    retry.run(
+        &format!(
+            "place windows {}",
+            layout
+                .iter()
+                .map(|p| format!("0x{:x}", p.window))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ),
+        || {
+            let mut batch: Vec<Dispatch> = Vec::new();
+            for p in pending.borrow().iter() {
+                batch.extend(
+                    float
+                        .borrow()
+                        .iter()
+                        .filter(|(w, _)| *w == p.window)
+                        .map(|(_, cmd)| cmd.clone()),
+                );
+                if let Some(g) = p.rect {
+                    batch.push(Dispatch::Move(p.window, g[0], g[1]));
+                    batch.push(Dispatch::Resize(p.window, g[2], g[3]));
+                }
+            }
+            // setfloating and settiled can be sent again, but not togglefloating
+            float
+                .borrow_mut()
+                .retain(|(_, cmd)| !matches!(cmd, Dispatch::ToggleFloating(_)));
+            for p in layout.iter().filter(|p| Some(p.window) != focus) {
+                batch.push(Dispatch::Raise(p.window));
+            }
+            if let Some(f) = focus {
+                batch.push(Dispatch::Focus(f));
+                batch.push(Dispatch::Raise(f));
+            }
+            backend.execute_batch(&batch)
+        },
+        || {
+            let clients = backend.clients()?;
+            pending.borrow_mut().retain(|p| {
+                !clients.iter().any(|c| {
+                    c.address == p.window
+                        && match p.rect {
+                            None => !c.floating,
+                            Some(g) => {
+                                close_to(c.at, [g[0], g[1]]) && close_to(c.size, [g[2], g[3]])
+                            }
+                        }
+                })
+            });
+            let focused = match focus {
+                Some(f) => backend.active_window()?.map(|c| c.address) == Some(f),
+                None => true,
+            };
+            Ok(pending.borrow().is_empty() && focused)
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::quick;
+    use crate::MockBackend;
+
+    fn tiled(address: Window) -> Client {
+        Client {
+            address,
+            size: [100, 100],
+            ..Default::default()
+        }
+    }
+
+    fn placement(window: Window, float: Float) -> Placement {
+        Placement {
+            window,
+            rect: Some([10, 20, 300, 400]),
+            float,
+        }
+    }
+
+    const TILINGS: &str = r#"<displays area="workarea">
+        <display resolution="1920x1080">
+            <window nick="tex" geometry="0,0,50%,100%"/>
+            <window nick="bad" geometry="0,0,half"/>
+        </display>
+        <display resolution="*" float="set">
+            <window nick="tex" geometry="0,0,800,600"/>
+            <window nick="pdf" geometry="0.5,0,0.5,1"/>
+        </display>
+    </displays>"#;
+
+    #[test]
+    fn nick_falls_back_to_any_resolution() {
+        let tilings: Displays = serde_xml_rs::from_str(TILINGS).unwrap();
+        let tex = get_geometry(&tilings, "tex", "1920x1080").unwrap().unwrap();
+        assert_eq!(tex.geometry, "0,0,50%,100%".parse().unwrap());
+        assert_eq!((tex.area, tex.float), (Area::WorkArea, Float::No));
+        let pdf = get_geometry(&tilings, "pdf", "1920x1080").unwrap().unwrap();
+        assert_eq!(pdf.float, Float::Set);
+        assert!(get_geometry(&tilings, "web", "1920x1080")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn invalid_geometry_is_an_error() {
+        let tilings: Displays = serde_xml_rs::from_str(TILINGS).unwrap();
+        match get_geometry(&tilings, "bad", "1920x1080") {
+            Err(WintError::Tilings(message)) => assert!(message.starts_with("nick bad:")),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn tiled_window_is_floated_then_placed() {
+        let backend = MockBackend::new(vec![tiled(1)], vec![Monitor::default()]);
+        let layout = [placement(1, Float::Toggle)];
+        do_resize(&backend, &layout, Some(1), &quick()).unwrap();
+        assert_eq!(
+            backend.batches(),
+            [vec![
+                Dispatch::ToggleFloating(1),
+                Dispatch::Move(1, 10, 20),
+                Dispatch::Resize(1, 300, 400),
+                Dispatch::Focus(1),
+                Dispatch::Raise(1),
+            ]]
+        );
+    }
+
+    #[test]
+    fn toggle_is_not_sent_again_while_hyprland_lags() {
+        let mut backend = MockBackend::new(vec![tiled(1)], vec![Monitor::default()]);
+        backend.lag = 3;
+        let layout = [placement(1, Float::Toggle)];
+        do_resize(&backend, &layout, Some(1), &quick()).unwrap();
+        let toggles = backend
+            .dispatched()
+            .iter()
+            .filter(|cmd| **cmd == Dispatch::ToggleFloating(1))
+            .count();
+        assert_eq!(toggles, 1);
+        assert!(backend.clients.borrow()[0].floating);
+    }
+
+    #[test]
+    fn others_are_raised_before_the_focus() {
+        let backend = MockBackend::new(vec![tiled(1), tiled(2)], vec![Monitor::default()]);
+        let layout = [
+            placement(1, Float::No),
+            Placement {
+                window: 2,
+                rect: None,
+                float: Float::No,
+            },
+        ];
+        do_resize(&backend, &layout, Some(1), &quick()).unwrap();
+        assert_eq!(
+            backend.dispatched(),
+            [
+                Dispatch::Move(1, 10, 20),
+                Dispatch::Resize(1, 300, 400),
+                Dispatch::Raise(2),
+                Dispatch::Focus(1),
+                Dispatch::Raise(1),
+            ]
+        );
+    }
+
+    /// A HiDPI monitor to the right of a 1920 pixels wide one, under a 30 pixels bar
+    fn hidpi() -> Monitor {
+        Monitor {
+            width: 2560,
+            height: 1440,
+            scale: 2.0,
+            x: 1920,
+            reserved: [0, 30, 0, 0],
+            ..Default::default()
+        }
+    }
+
+    fn tile(geometry: &str, area: Area) -> Tile {
+        Tile {
+            geometry: geometry.parse().unwrap(),
+            area,
+            float: Float::No,
+        }
+    }
+
+    #[test]
+    fn monitor_area_is_scaled_and_offset() {
+        let none = Decorations::default();
+        let m = hidpi();
+        assert_eq!(
+            place(&tile("0,0,50%,100%", Area::Monitor), &m, &none),
+            [1920, 0, 640, 720]
+        );
+        // pixels are physical pixels of the monitor
+        assert_eq!(
+            place(&tile("100,200,800,600", Area::Monitor), &m, &none),
+            [1970, 100, 400, 300]
+        );
+    }
+
+    #[test]
+    fn work_area_leaves_out_bars_gaps_and_borders() {
+        let decorations = Decorations {
+            gaps_out: [5, 10, 15, 20],
+            border_size: 2,
+        };
+        let m = hidpi();
+        // 1250x670 logical pixels are left, from 1940,35
+        assert_eq!(
+            place(&tile("0,0,0.5,1.0", Area::WorkArea), &m, &decorations),
+            [1942, 37, 621, 666]
+        );
+        assert_eq!(
+            place(&tile("50%,0,50%,100%", Area::WorkArea), &m, &decorations),
+            [2567, 37, 621, 666]
+        );
+    }
+
+    #[test]
+    fn rotated_monitor() {
+        let m = Monitor {
+            width: 1920,
+            height: 1080,
+            scale: 1.0,
+            transform: 1,
+            ..Default::default()
+        };
+        assert_eq!(m.resolution(), "1080x1920");
+        assert_eq!(
+            place(
+                &tile("0,50%,100%,50%", Area::Monitor),
+                &m,
+                &Decorations::default()
+            ),
+            [0, 960, 1080, 960]
+        );
+    }
+}
@@ -4,22 +4,48 @@ extern crate gdk;
 extern crate gdk_sys;
 extern crate gio;
 
-use clap::{App, Arg};
+use clap::{App, Arg, ArgGroup, SubCommand};
 use glib::clone;
 use glib::signal::Propagation;
 use gtk::glib;
 use gtk::prelude::*;
-use std::process::Command;
 
 use crate::gdk::prelude::{ApplicationExt, ApplicationExtManual};
+use hyprwinter::script::{self, WindowMatch};
+use hyprwinter::state::Stored;
 use hyprwinter::{
-    check_css, check_tilings, get_conf, get_config_dir, get_wm_data, go_to_window, make_vbox,
-    Config, TMPFile,
+    act_on_window, check_css, check_tilings, dim_rows, filter_rows, get_conf, get_config_dir,
+    get_history, get_wm_data, go_to_window, hint_store_path, hinted_clients,
+    history::History, make_vbox, select_row, tmpfile_path, Action, Backend, Client, Config,
+    Hyprland, Window,
 };
-use std::cell::RefCell;
-use std::io::{BufRead, Write};
+use hyprwinter::{list, menu};
+use std::cell::{Cell, RefCell};
 use std::path::Path;
 use std::rc::Rc;
+use std::time::Duration;
+
+/// How long winj waits for another space before going to the selected window
+const WALK_PAUSE: Duration = Duration::from_millis(350);
+
+/// The windows with their hints, as winj shows them; new sticky hints are saved
+fn hint_windows<'a>(
+    wins: &'a [Client],
+    desktop: Option<i64>,
+    conf: &Config,
+    store_path: Option<&Path>,
+    history: &RefCell<History>,
+) -> Vec<(String, &'a Client)> {
+    history.borrow_mut().prune(wins);
+    hinted_clients(
+        wins,
+        desktop,
+        &conf.blacklist,
+        &conf.hints,
+        store_path,
+        &history.borrow(),
+    )
+}
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     let clops = App::new("wmjump")
@@ -30,45 +56,129 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("only show windows on the current desktop")
                 .short("c"),
         )
+        .arg(
+            Arg::with_name("cycle")
+                .help("alt-tab style: start with the previous window selected, Tab selects the next one, releasing the modifier jumps")
+                .long("cycle"),
+        )
+        .arg(
+            Arg::with_name("menu")
+                .help("show the windows with this dmenu-compatible launcher, e.g. \"fuzzel --dmenu\", instead of winj's own window")
+                .long("menu")
+                .takes_value(true)
+                .value_name("COMMAND"),
+        )
+        .subcommand(
+            SubCommand::with_name("focus")
+                .about("jump to the window given by class or title, without showing the list; the most recently used one if several match")
+                .arg(
+                    Arg::with_name("class")
+                        .help("the class of the window")
+                        .long("class")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("title-regex")
+                        .help("a regex matching the title of the window")
+                        .long("title-regex")
+                        .takes_value(true),
+                )
+                .group(
+                    ArgGroup::with_name("window")
+                        .args(&["class", "title-regex"])
+                        .multiple(true)
+                        .required(true),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("print the windows winj would show, with their hints")
+                .arg(
+                    Arg::with_name("current")
+                        .help("only list windows on the current desktop")
+                        .short("c"),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .help("print a JSON array (the default)")
+                        .long("json"),
+                )
+                .arg(
+                    Arg::with_name("tsv")
+                        .help("print tab-separated lines: hint, address, workspace, class, title, style class")
+                        .long("tsv")
+                        .conflicts_with("json"),
+                ),
+        )
         .get_matches();
     let config_dir = get_config_dir();
     let conf: Config = get_conf()?;
     let maxlen = conf.maxwidth;
-    let tmpfilename = match conf.tmpfile {
-        TMPFile::Custom(x) => format!("{}", x),
-        TMPFile::InXdgRuntime => match std::env::vars()
-            .into_iter()
-            .filter(|(k, _v)| k == "XDG_RUNTIME_DIR")
-            .next()
-        {
-            Some(x) => format!("{}/winterreise", x.1),
-            None => panic!("system does not have XDG_RUNTIME_DIR; please use custom <tmpfile><custom>...</custom></tmpfile> or <tmpfile><in_tmp/></custom> option in config"),
-        },
-        TMPFile::InTmp => String::from("/tmp/winterreise"),
-    };
-    let tmpfile = std::fs::OpenOptions::new()
-        .read(true)
-        .open(&tmpfilename)
-        .unwrap_or_else(|_e| {
-            std::fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .open(&tmpfilename)
-                .unwrap()
-        });
-    let prev_win = match std::io::BufReader::new(&tmpfile).lines().into_iter().next() {
-        Some(Ok(x)) => Some(x),
-        _ => None,
-    };
-    let tmpfile = std::fs::OpenOptions::new()
-        .read(true)
-        .write(true)
-        .truncate(true)
-        .create(true)
-        .open(&tmpfilename)
-        .unwrap();
-    let tmpfile = Rc::new(RefCell::new(tmpfile));
+    let retry = conf.retry();
+    let tmpfilename = tmpfile_path(&conf.tmpfile);
+    let store_path = conf.hints.sticky.as_ref().map(|_| hint_store_path(&conf.tmpfile));
+    let history = Rc::new(RefCell::new(get_history(&conf)));
+    let history_limit = conf.history;
+    let tmpfilename = Rc::new(tmpfilename);
     let space_between_buttons = conf.space_between_buttons;
+    if let Some(focus) = clops.subcommand_matches("focus") {
+        let matcher = WindowMatch::new(focus.value_of("class"), focus.value_of("title-regex"));
+        let result = matcher.and_then(|matcher| {
+            let backend = Hyprland::from_env()?;
+            let active = backend.active_window()?.map(|c| c.address).unwrap_or(0);
+            let target = script::focus(&backend, &matcher, &history.borrow(), &retry)?;
+            let recorded = History::update(&tmpfilename, history_limit, |h| h.jump(active, target));
+            if let Err(e) = recorded {
+                println!("ERROR: could not save the history: {}", e);
+            }
+            Ok(())
+        });
+        if let Err(e) = result {
+            eprintln!("ERROR: {}", e);
+            std::process::exit(e.exit_code());
+        }
+        return Ok(());
+    }
+    if let Some(listing) = clops.subcommand_matches("list") {
+        let backend = Hyprland::from_env()?;
+        let (wins, _monitors, desktop, _active) = get_wm_data(&backend);
+        let desktop = listing.is_present("current").then_some(desktop);
+        let hinted = hint_windows(&wins, desktop, &conf, store_path.as_deref(), &history);
+        let entries = list::entries(&hinted);
+        if listing.is_present("tsv") {
+            print!("{}", list::to_tsv(&entries));
+        } else {
+            println!("{}", list::to_json(&entries)?);
+        }
+        return Ok(());
+    }
+    let menu_command = clops
+        .value_of("menu")
+        .map(String::from)
+        .or(conf.menu.clone());
+    if let Some(command) = menu_command {
+        let backend = Hyprland::from_env()?;
+        let (wins, _monitors, desktop, active) = get_wm_data(&backend);
+        let desktop = clops.is_present("current").then_some(desktop);
+        let hinted = hint_windows(&wins, desktop, &conf, store_path.as_deref(), &history);
+        let choice = match menu::choose(&command, &menu::lines(&hinted, maxlen))? {
+            Some(choice) => choice,
+            None => return Ok(()),
+        };
+        match menu::parse_choice(&choice, &hinted) {
+            Some(client) => {
+                let target = client.address;
+                let recorded =
+                    History::update(&tmpfilename, history_limit, |h| h.jump(active, target));
+                if let Err(e) = recorded {
+                    println!("ERROR: could not save the history: {}", e);
+                }
+                go_to_window(&backend, target, &retry)?;
+            }
+            None => println!("ERROR: no window for {}", choice),
+        }
+        return Ok(());
+    }
 
     let application = gtk::Application::builder()
         .application_id("com.andreimikhailov.winterreise")
@@ -78,8 +188,12 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let tilings = Path::join(&config_dir, "tilings.xml");
     check_tilings(&tilings);
     let blacklist = Rc::new(conf.blacklist);
+    let hints_conf = conf.hints;
+    let start_in_search = conf.search;
+    let actions = Rc::new(conf.actions);
+    let backend: Rc<dyn Backend> = Rc::new(Hyprland::from_env()?);
     application.connect_activate(move |app| {
-        let (wins, _geom, desktop, active) = get_wm_data();
+        let (wins, _geom, desktop, active) = get_wm_data(backend.as_ref());
         let provider = gtk::CssProvider::new();
         match css.to_str() {
             Some(x) => {
@@ -100,72 +214,250 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         window.set_type_hint(gdk::WindowTypeHint::Dialog);
         window.style_context().add_class(if clops.is_present("current") { "main_window_currentonly" } else { "main_window" });
         window.connect_focus_out_event(clone!(@weak app => @default-return Propagation::Proceed, move |_w,_e| { app.quit(); return Propagation::Stop; }));
-        let (vbox, charhints) = make_vbox(
+        history.borrow_mut().prune(&wins);
+        let hinted = hinted_clients(
             &wins,
             if clops.is_present("current") { Some(desktop) } else { None },
-            space_between_buttons,
-            maxlen,
             &blacklist,
-            &active
+            &hints_conf,
+            store_path.as_deref(),
+            &history.borrow(),
             );
+        let (vbox, charhints, rows) = make_vbox(&hinted, space_between_buttons, maxlen, &active);
         window.add(&vbox);
         let hints = Rc::new(charhints);
-        let tmpfile = tmpfile.clone();
-        let pw = Rc::new(prev_win.clone());
-        window.connect_key_press_event(clone!(@weak app => @default-return Propagation::Proceed, move |_w,e| {
+        let letters = hints_conf.letters();
+        let rows = Rc::new(rows);
+        let typed = Rc::new(RefCell::new(String::new()));
+        let backend = backend.clone();
+        // the history is only written when winj actually goes somewhere
+        let remember = clone!(@strong tmpfilename => move |target: Option<Window>| {
+            let recorded = History::update(&tmpfilename, history_limit, |history| {
+                history.push(active);
+                if let Some(t) = target { history.push(t); }
+            });
+            if let Err(e) = recorded { println!("ERROR: could not save the history: {}", e); }
+        });
+        let act = clone!(@weak app, @strong remember, @strong backend => move |target: Window, action: Action| {
+            app.quit();
+            if action == Action::Jump || action == Action::Fullscreen { remember(Some(target)); }
+            if let Err(e) = act_on_window(backend.as_ref(), target, action, &retry) {
+                eprintln!("ERROR: {}", e);
+            }
+        });
+        let jump = clone!(@strong act => move |target: Window| act(target, Action::Jump));
+        // space walks back through the history, Return jumps to the selected window
+        let walk = Rc::new(history.borrow().previous(active));
+        let selected: Rc<Cell<Option<usize>>> = Rc::new(Cell::new(None));
+        let step = clone!(@strong walk, @strong selected, @strong rows, @strong hints => move |back: bool| {
+            if walk.is_empty() { return; }
+            let i = match (selected.get(), back) {
+                (None, _) => 0,
+                (Some(i), false) => (i + 1) % walk.len(),
+                (Some(i), true) => (i + walk.len() - 1) % walk.len(),
+            };
+            println!("-- previous window {} is {:#x}", i + 1, walk[i]);
+            selected.set(Some(i));
+            select_row(&rows, &hints, walk[i]);
+        });
+        let go_to_selected = clone!(@weak app, @strong walk, @strong selected, @strong jump => move || {
+            match selected.get() {
+                Some(i) => jump(walk[i]),
+                None => app.quit(),
+            }
+        });
+        // a single space goes to the previous window; more spaces in a row walk further back,
+        // and winj goes to the selected window once they stop
+        let pause: Rc<RefCell<Option<glib::SourceId>>> = Rc::new(RefCell::new(None));
+        let cancel_pause = clone!(@strong pause => move || {
+            if let Some(id) = pause.borrow_mut().take() { id.remove(); }
+        });
+        let walk_back = clone!(@strong step, @strong go_to_selected, @strong pause, @strong cancel_pause => move || {
+            step(false);
+            cancel_pause();
+            let id = glib::timeout_add_local_once(WALK_PAUSE, clone!(@strong pause, @strong go_to_selected => move || {
+                pause.borrow_mut().take();
+                go_to_selected();
+            }));
+            *pause.borrow_mut() = Some(id);
+        });
+        let searching = Rc::new(Cell::new(false));
+        let search = gtk::Entry::new();
+        search.style_context().add_class("wmjump_search_entry");
+        search.set_no_show_all(true);
+        vbox.add(&search);
+        search.connect_changed(clone!(@weak vbox, @strong rows, @strong hints => move |search| {
+            filter_rows(&vbox, &rows, &hints, &search.text());
+        }));
+        search.connect_activate(clone!(@weak vbox, @strong rows, @strong hints, @strong jump => move |search| {
+            if let Some(s) = filter_rows(&vbox, &rows, &hints, &search.text()) {
+                jump(s.address);
+            }
+        }));
+        let start_search = clone!(@weak search, @strong searching => move || {
+            searching.set(true);
+            search.show();
+            search.grab_focus();
+        });
+        let cycle = clops.is_present("cycle");
+        // an action key, then a hint, applies the action to the window;
+        // the move key, then a hint, then a digit sends the window to that workspace
+        let pending = Cell::new(Action::Jump);
+        let moving = Rc::new(Cell::new(false));
+        let chosen: Rc<Cell<Option<Window>>> = Rc::new(Cell::new(None));
+        // ':' asks for any workspace: 10 and beyond, a name, or special:NAME
+        let workspace = gtk::Entry::new();
+        workspace.style_context().add_class("wmjump_workspace_entry");
+        workspace.set_placeholder_text(Some("workspace"));
+        workspace.set_no_show_all(true);
+        vbox.add(&workspace);
+        workspace.connect_activate(clone!(@weak app, @strong moving, @strong chosen, @strong remember, @strong backend => move |workspace| {
+            let target = workspace.text().trim().to_string();
+            if target.is_empty() { return; }
+            if moving.get() {
+                if let Some(win) = chosen.get() {
+                    app.quit();
+                    if let Err(e) = retry.move_to_workspace(backend.as_ref(), win, &target, true) {
+                        eprintln!("ERROR: {}", e);
+                    }
+                }
+                return;
+            }
+            app.quit();
+            remember(None);
+            if let Err(e) = backend.switch_workspace(&target) {
+                eprintln!("ERROR: {}", e);
+            }
+        }));
+        let start_workspace = clone!(@weak workspace, @strong searching => move || {
+            searching.set(true);
+            workspace.show();
+            workspace.grab_focus();
+        });
+        window.connect_key_press_event(clone!(@weak app, @strong start_search, @strong start_workspace, @strong step, @strong go_to_selected, @strong walk_back, @strong cancel_pause, @strong actions => @default-return Propagation::Proceed, move |w,e| {
             let keyval = e.keyval();
-            let _keystate = e.state();
+            let keystate = e.state();
             if *keyval == gdk_sys::GDK_KEY_Escape as u32 {
                 app.quit();
                 return Propagation::Stop;
             }
-            if *keyval == gdk_sys::GDK_KEY_space as u32 {
-                app.quit();
-                match pw.as_ref() {
-                    Some(w) =>  {
-                        println!("-- previous window was {}",w);
-                        let _ = Command::new("hyprctl")
-                            .arg("dispatch")
-                            .arg("focuswindow")
-                            .arg(format!("address:{}", w))
-                            .spawn();
-                        let _ = Command::new("hyprctl")
-                            .arg("dispatch")
-                            .arg("alterzorder")
-                            .arg(format!("top,address:{}", w))
-                            .status()
-                            .expect("Failed to raise window");
-                        tmpfile.borrow_mut().write(&format!("{:#x}",active).into_bytes()[..]).expect("failed writing to tmpfile");
-                    }
-                    None => ()
-                }
+            if searching.get() {
+                // the keys go to the search or workspace entry
+                return Propagation::Proceed;
+            }
+            if *keyval == gdk_sys::GDK_KEY_space as u32 && !cycle {
+                walk_back();
+                return Propagation::Stop;
+            }
+            // any other key leaves the selected window to Return
+            cancel_pause();
+            if *keyval == gdk_sys::GDK_KEY_space as u32 || (cycle && *keyval == gdk_sys::GDK_KEY_Tab as u32) {
+                step(false);
+                return Propagation::Stop;
+            }
+            if cycle && *keyval == gdk_sys::GDK_KEY_ISO_Left_Tab as u32 {
+                step(true);
                 return Propagation::Stop;
             }
-            let a = (format!("{}",*keyval)).parse::<u8>();
-            match a {
-                Ok(aa) => {
+            if *keyval == gdk_sys::GDK_KEY_Return as u32 || *keyval == gdk_sys::GDK_KEY_KP_Enter as u32 {
+                go_to_selected();
+                return Propagation::Stop;
+            }
+            if *keyval == gdk_sys::GDK_KEY_BackSpace as u32 {
+                typed.borrow_mut().pop();
+                dim_rows(&rows, &typed.borrow());
+                return Propagation::Stop;
+            }
+            match keyval.to_unicode() {
+                Some(d) if ('1'..='9').contains(&d) && moving.get() => {
+                    if let Some(win) = chosen.get() {
+                        app.quit();
+                        // with Control, follow the window there
+                        let follow = keystate.contains(gdk::ModifierType::CONTROL_MASK);
+                        if follow { remember(Some(win)); }
+                        if let Err(e) = retry.move_to_workspace(backend.as_ref(), win, &d.to_string(), !follow) {
+                            eprintln!("ERROR: {}", e);
+                        }
+                    }
+                    return Propagation::Stop;
+                }
+                Some(d) if ('1'..='9').contains(&d) && typed.borrow().is_empty() => {
                     app.quit();
-                    if aa < 97 && aa > 48 {
-                        tmpfile.borrow_mut().write(&format!("{:#x}",active).into_bytes()[..]).expect("failed writing to tmpfile");
-                        let new_desktop = (aa - 48) as u32;
-                        let _ = Command::new("hyprctl")
-                            .arg("dispatch")
-                            .arg("workspace")
-                            .arg(format!("{new_desktop}"))
-                            .spawn();
+                    remember(None);
+                    let _ = backend.switch_workspace(&d.to_string());
+                    return Propagation::Stop;
+                }
+                Some('/') if !letters.contains(&'/') && typed.borrow().is_empty() => {
+                    start_search();
+                    return Propagation::Stop;
+                }
+                Some(':') if !letters.contains(&':') && typed.borrow().is_empty() => {
+                    start_workspace();
+                    return Propagation::Stop;
+                }
+                Some(c) if !letters.contains(&c) && typed.borrow().is_empty() && (actions.is_move(c) || actions.action(c).is_some()) => {
+                    match actions.action(c) {
+                        Some(action) => {
+                            moving.set(false);
+                            pending.set(action);
+                            w.set_title(action.title());
+                        }
+                        None => {
+                            moving.set(true);
+                            pending.set(Action::Jump);
+                            w.set_title("Move to workspace...");
+                        }
+                    }
+                    return Propagation::Stop;
+                }
+                Some(c) if letters.contains(&c) => {
+                    let prefix = format!("{}{}", typed.borrow(), c);
+                    if let Some(s) = hints.get(&prefix) {
+                        if moving.get() {
+                            chosen.set(Some(s.address));
+                            typed.borrow_mut().clear();
+                            dim_rows(&rows, "");
+                            select_row(&rows, &hints, s.address);
+                            w.set_title(&format!("Move {} to workspace...", s.class));
+                        } else {
+                            act(s.address, pending.get());
+                        }
                         return Propagation::Stop;
-                    } else  if let Some(s) = &hints.get(&(aa - 97)) {
-                        tmpfile.borrow_mut().write(&format!("{:#x}",active).into_bytes()[..]).expect("failed writing to tmpfile");
-                        go_to_window(**s);
+                    } else if hints.keys().any(|h| h.starts_with(&prefix)) {
+                        dim_rows(&rows, &prefix);
+                        *typed.borrow_mut() = prefix;
                         return Propagation::Stop;
                     } else {
+                        app.quit();
                         return Propagation::Proceed;
                     }
-                },
+                }
                 _ => { return Propagation::Proceed; }
             }
         }));
+        if cycle {
+            // the modifier which was held to launch winj
+            let modifiers = [
+                gdk_sys::GDK_KEY_Alt_L, gdk_sys::GDK_KEY_Alt_R,
+                gdk_sys::GDK_KEY_Super_L, gdk_sys::GDK_KEY_Super_R,
+                gdk_sys::GDK_KEY_Control_L, gdk_sys::GDK_KEY_Control_R,
+                gdk_sys::GDK_KEY_Meta_L, gdk_sys::GDK_KEY_Meta_R,
+            ];
+            window.connect_key_release_event(move |_w,e| {
+                if modifiers.iter().any(|m| *e.keyval() == *m as u32) {
+                    go_to_selected();
+                    return Propagation::Stop;
+                }
+                Propagation::Proceed
+            });
+        }
         window.show_all();
+        if start_in_search {
+            start_search();
+        }
+        if cycle {
+            step(false);
+        }
     });
     let empty: Vec<String> = vec![];
 
@@ -1,3 +1,4 @@
+extern crate clap;
 extern crate dirs;
 extern crate gdk;
 extern crate gdk_sys;
@@ -6,133 +7,204 @@ extern crate glib;
 extern crate gtk;
 
 use crate::gdk::prelude::{ApplicationExt, ApplicationExtManual};
+use clap::{App, Arg, ArgGroup, ArgMatches, SubCommand};
 use glib::clone;
 use glib::signal::Propagation;
 use gtk::prelude::*;
-use std::fs::File;
-use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::collections::HashMap;
+use std::path::Path;
 use std::rc::Rc;
-use std::thread;
-use std::time::Duration;
 
+use hyprwinter::command::{parse_command, CommandError};
+use hyprwinter::layout::{fill_slots, find_layout, focused_monitor, place_slots};
+use hyprwinter::save::{save_nicks, saved_nick, valid_nick, SavedNick};
+use hyprwinter::script::{self, WindowMatch};
+use hyprwinter::tiling::{do_resize, load_tilings, place_client, Decorations, Placement};
 use hyprwinter::{
-    check_css, check_tilings, get_conf, get_config_dir, get_wm_data, make_vbox, Config, Monitor,
-    Window,
+    check_css, check_tilings, get_conf, get_config_dir, get_history, get_wm_data, hint_store_path,
+    hinted_clients, make_vbox, monitor_of, Backend, Client, Config, Hyprland, Monitor, WintError,
 };
 
-#[macro_use]
-extern crate serde_derive;
-extern crate serde;
-extern crate serde_xml_rs;
-
-#[derive(Debug, Deserialize)]
-struct WindowSimple {
-    #[serde(rename = "@nick", default)]
-    pub nick: String,
-    #[serde(rename = "@geometry", default)]
-    pub geometry: String,
-}
-
-#[derive(Debug, Deserialize)]
-struct Display {
-    #[serde(rename = "@resolution", default)]
-    pub resolution: String,
-
-    #[serde(rename = "window", default)]
-    pub windows: Vec<WindowSimple>,
-}
-
-#[derive(Debug, Deserialize, Default)]
-#[serde(rename = "displays", default)]
-struct Displays {
-    #[serde(rename = "display", default)]
-    pub items: Vec<Display>,
+/// The `--class` and `--title-regex` pairs of `wint apply`, in command line order
+fn assignments(clops: &ArgMatches) -> Result<Vec<(WindowMatch, String)>, WintError> {
+    let mut pairs = Vec::new();
+    for name in ["class", "title-regex"] {
+        if let (Some(values), Some(indices)) = (clops.values_of(name), clops.indices_of(name)) {
+            let values = values.collect::<Vec<&str>>();
+            let indices = indices.collect::<Vec<usize>>();
+            for (i, pair) in values.chunks(2).enumerate() {
+                let matcher = match name {
+                    "class" => WindowMatch::new(Some(pair[0]), None)?,
+                    _ => WindowMatch::new(None, Some(pair[0]))?,
+                };
+                pairs.push((indices[2 * i], matcher, pair[1].to_string()));
+            }
+        }
+    }
+    pairs.sort_by_key(|p| p.0);
+    Ok(pairs.into_iter().map(|(_, m, nick)| (m, nick)).collect())
 }
 
-fn get_geometry(xml_path: &PathBuf, nick: String, geom: &String) -> Option<Vec<u32>> {
-    let tilings: Displays = serde_xml_rs::from_reader(File::open(xml_path).unwrap()).unwrap();
-    tilings
-        .items
-        .iter()
-        .filter(|disp| &disp.resolution == geom)
-        .next()
-        .and_then(|x| {
-            x.windows
-                .iter()
-                .filter(|w| w.nick == nick)
-                .next()
-                .map(|ni| {
-                    ni.geometry
-                        .split(",")
-                        .map(|s| str::parse::<u32>(s).unwrap())
-                        .collect()
-                })
+/// The command `@NAME` tiles the windows with the layout; the hints after the name
+/// choose, in order, the windows of the slots which several windows match
+fn layout_placements(
+    command: &str,
+    charhints: &HashMap<String, Client>,
+    xml_path: &Path,
+    monitors: &[Monitor],
+    decorations: &Decorations,
+) -> Result<Vec<Placement>, CommandError> {
+    let whole = |message: String| CommandError::new(message, 0, command.chars().count());
+    let mut words = command.split_whitespace();
+    let name = words.next().unwrap_or("").trim_start_matches('@');
+    let tilings = load_tilings(xml_path).map_err(|e| whole(e.to_string()))?;
+    let monitor = focused_monitor(monitors);
+    let (display, layout) =
+        find_layout(&tilings, name, &monitor.resolution()).ok_or_else(|| {
+            whole(format!(
+                "no layout {} for screen resolution {}",
+                name,
+                monitor.resolution()
+            ))
+        })?;
+    let matchers = layout.matchers().map_err(|e| whole(e.to_string()))?;
+    let mut picks = words
+        .map(|hint| {
+            charhints
+                .get(hint)
+                .ok_or_else(|| whole(format!("no window has the hint {}", hint)))
         })
-}
-fn do_resize(wid: Window, g: &Vec<u32>, geom: &Monitor) {
-    //println!("Resizing window address:0x{:x}", wid);
-    let _ = Command::new("hyprctl")
-        .arg("dispatch")
-        .arg("focuswindow")
-        .arg(format!("address:0x{:x}", wid))
-        .status()
-        .expect("Failed to focus window");
-
-    thread::sleep(Duration::from_millis(100));
-
-    let _ = Command::new("hyprctl")
-        .arg("dispatch")
-        .arg("movewindowpixel")
-        .arg("exact")
-        .arg(format!("{}", (g[0] as f32 / geom.scale).round()))
-        .arg(format!(
-            "{},address:0x{:x}",
-            (g[1] as f32 / geom.scale).round(),
-            wid
-        ))
-        .status()
-        .expect("Failed to move window");
-
-    let _ = Command::new("hyprctl")
-        .arg("dispatch")
-        .arg("focuswindow")
-        .arg(format!("address:0x{:x}", wid))
-        .status()
-        .expect("Failed to focus window");
-
-    thread::sleep(Duration::from_millis(100));
-
-    let _ = Command::new("hyprctl")
-        .arg("dispatch")
-        .arg("resizewindowpixel")
-        .arg("exact")
-        .arg(format!("{}", (g[2] as f32 / geom.scale).round()))
-        .arg(format!(
-            "{},address:0x{:x}",
-            (g[3] as f32 / geom.scale).round(),
-            wid
-        ))
-        .status()
-        .expect("Failed to resize window");
-
-    thread::sleep(Duration::from_millis(100));
-
-    let _ = Command::new("hyprctl")
-        .arg("dispatch")
-        .arg("alterzorder")
-        .arg(format!("top,address:0x{:x}", wid))
-        .status()
-        .expect("Failed to raise window");
+        .collect::<Result<Vec<&Client>, CommandError>>()?
+        .into_iter();
+    let mut hinted = charhints.iter().collect::<Vec<(&String, &Client)>>();
+    hinted.sort_by_key(|(hint, _)| hint.to_string());
+    let hint_of = |c: &Client| {
+        hinted
+            .iter()
+            .find(|(_, w)| w.address == c.address)
+            .map(|(hint, _)| hint.to_string())
+            .unwrap_or_default()
+    };
+    let windows = hinted.iter().map(|(_, c)| *c).collect::<Vec<&Client>>();
+    let filled = fill_slots(&matchers, &windows, |matcher, left| match picks.next() {
+        Some(c) if left.iter().any(|w| w.address == c.address) => Ok(c),
+        Some(c) => Err(whole(format!("window {} is not {}", hint_of(c), matcher))),
+        None => Err(whole(format!(
+            "several windows are {}: add one of {} after @{}",
+            matcher,
+            left.iter()
+                .map(|c| hint_of(c))
+                .collect::<Vec<String>>()
+                .join(", "),
+            name
+        ))),
+    })?;
+    let placements = place_slots(&tilings, display, layout, &filled, monitor, decorations)
+        .map_err(|e| whole(e.to_string()))?;
+    if placements.is_empty() {
+        return Err(whole(format!("no window for the layout {}", name)));
+    }
+    Ok(placements)
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clops = App::new("wint")
+        .author("Andrei Mikhailov")
+        .about("Window tiling")
+        .arg(
+            Arg::with_name("save")
+                .help("save where the windows are as nicks in tilings.xml, e.g. atex saves the geometry of window a as tex")
+                .long("save"),
+        )
+        .subcommand(
+            SubCommand::with_name("apply")
+                .about("tile the windows given by class or title, without showing the list")
+                .arg(
+                    Arg::with_name("class")
+                        .help("give the nick to the window of this class")
+                        .long("class")
+                        .value_names(&["CLASS", "NICK"])
+                        .number_of_values(2)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("title-regex")
+                        .help("give the nick to the window whose title matches")
+                        .long("title-regex")
+                        .value_names(&["REGEX", "NICK"])
+                        .number_of_values(2)
+                        .multiple(true),
+                )
+                .arg(
+                    Arg::with_name("layout")
+                        .help("tile the windows of the current workspace with this layout")
+                        .long("layout")
+                        .takes_value(true)
+                        .value_name("NAME")
+                        .conflicts_with_all(&["class", "title-regex"]),
+                )
+                .group(
+                    ArgGroup::with_name("windows")
+                        .args(&["class", "title-regex", "layout"])
+                        .multiple(true)
+                        .required(true),
+                ),
+        )
+        .get_matches();
     let config_dir = get_config_dir();
     let conf: Config = get_conf().expect("Could not read the configuration file");
     let maxlen = conf.maxwidth;
+    let retry = conf.retry();
+    let save = clops.is_present("save");
+    let history = get_history(&conf);
     let blacklist = Rc::new(conf.blacklist);
+    let store_path = conf
+        .hints
+        .sticky
+        .as_ref()
+        .map(|_| hint_store_path(&conf.tmpfile));
+    let hints_conf = conf.hints;
     let space_between_buttons = conf.space_between_buttons;
-    let (wins, geom, desktop, active) = get_wm_data();
+    if let Some(apply) = clops.subcommand_matches("apply") {
+        let xml_path = Path::join(&config_dir, "tilings.xml");
+        check_tilings(&xml_path);
+        let result = Hyprland::from_env().and_then(|backend| {
+            let decorations = Decorations::query(&backend).unwrap_or_else(|e| {
+                println!("ERROR: could not read gaps and borders: {}", e);
+                Decorations::default()
+            });
+            match apply.value_of("layout") {
+                Some(name) => script::apply_layout(
+                    &backend,
+                    &xml_path,
+                    name,
+                    &blacklist,
+                    &decorations,
+                    &history,
+                    &retry,
+                ),
+                None => script::apply(
+                    &backend,
+                    &xml_path,
+                    &assignments(apply)?,
+                    &decorations,
+                    &history,
+                    &retry,
+                ),
+            }
+        });
+        if let Err(e) = result {
+            eprintln!("ERROR: {}", e);
+            std::process::exit(e.exit_code());
+        }
+        return Ok(());
+    }
+    let backend: Rc<dyn Backend> = Rc::new(Hyprland::from_env()?);
+    let (wins, monitors, desktop, active) = get_wm_data(backend.as_ref());
+    let decorations = Rc::new(Decorations::query(backend.as_ref()).unwrap_or_else(|e| {
+        println!("ERROR: could not read gaps and borders: {}", e);
+        Decorations::default()
+    }));
 
     let application = gtk::Application::builder()
         .application_id("com.andreimikhailov.winterreise")
@@ -163,7 +235,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             _ => (),
         };
         let window = gtk::ApplicationWindow::new(app);
-        window.set_title("Tile");
+        window.set_title(if save { "Save as tiling" } else { "Tile" });
         window.set_type_hint(gdk::WindowTypeHint::Dialog);
         window.style_context().add_class("main_window_tile");
         window.connect_key_press_event(
@@ -177,41 +249,94 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }),
         );
 
-        let (vbox, charhints) = make_vbox(
+        let hinted = hinted_clients(
             &wins,
             Some(desktop),
-            space_between_buttons,
-            maxlen,
             &blacklist,
-            &active,
+            &hints_conf,
+            store_path.as_deref(),
+            &history,
         );
+        let (vbox, charhints, _rows) = make_vbox(&hinted, space_between_buttons, maxlen, &active);
         window.add(&vbox);
         let entry = gtk::Entry::new();
         entry.style_context().add_class("wmjump_cmd_entry");
-        let geom1 = Rc::clone(&geom);
+        let message = gtk::Label::new(None);
+        message.style_context().add_class("wmjump_cmd_message");
+        let monitors = Rc::clone(&monitors);
+        let decorations = Rc::clone(&decorations);
         let xml_path = Rc::clone(&xml_path);
-        entry.connect_activate(clone!(@weak entry, @weak app => move |_| {
+        let backend = Rc::clone(&backend);
+        entry.connect_changed(clone!(@weak message => move |entry| {
+            entry.style_context().remove_class("wmjump_cmd_error");
+            message.set_text("");
+        }));
+        entry.connect_activate(clone!(@weak entry, @weak message, @weak app => move |_| {
             let command : String = entry.text().to_string();
-            let tilings : Vec<(Window, Option<Vec<u32>>)> = command.split(" ").map(|com| {
-                let mut it = com.chars();
-                let charhint = it.next().unwrap();
-                let wid = *charhints.get(&(charhint as u8 - 97 as u8)).unwrap();
-                let tiling = it.collect::<String>();
-                let mg = get_geometry(&xml_path, tiling, &format!("{}x{}", geom1.width, geom1.height));
-                return (wid, mg)
-            }).collect();
-            app.quit();
-            for (wid, mg) in tilings.iter() {
-                match mg {
-                    Some(g) => do_resize(*wid, &g, &geom1),
-                    None => println!("No geometry found for window {} at screen resolution {}", wid, format!("{}x{}", geom1.width, geom1.height))
+            let hints : Vec<String> = charhints.keys().cloned().collect();
+            let show_error = |e: &CommandError| {
+                println!("ERROR: {}", e);
+                entry.style_context().add_class("wmjump_cmd_error");
+                entry.select_region(e.start as i32, e.end as i32);
+                message.set_text(&e.message);
+            };
+            let whole = |e: WintError| CommandError::new(e.to_string(), 0, command.chars().count());
+            if save {
+                let saved = load_tilings(&xml_path).map_err(whole).and_then(|tilings| {
+                    parse_command(&command, &hints)?.iter().map(|a| {
+                        if !valid_nick(&a.nick) {
+                            return Err(CommandError::new(format!("cannot save the nick {}", a.nick), a.nick_start, a.nick_end));
+                        }
+                        Ok(saved_nick(&tilings, &a.nick, &charhints[&a.hint], &monitors, &decorations))
+                    }).collect::<Result<Vec<SavedNick>, CommandError>>()
+                });
+                match saved.and_then(|saved| save_nicks(&xml_path, &saved).map_err(whole).map(|_| saved)) {
+                    Ok(saved) => {
+                        app.quit();
+                        for n in saved {
+                            println!("-- saved {} as {} for {}", n.nick, n.geometry, n.resolution);
+                        }
+                    }
+                    Err(e) => show_error(&e),
+                }
+                return;
+            }
+            let layout = if command.trim_start().starts_with('@') {
+                layout_placements(&command, &charhints, &xml_path, &monitors, &decorations)
+            } else {
+                load_tilings(&xml_path).map_err(whole).and_then(|tilings| {
+                    parse_command(&command, &hints)?.iter().map(|a| {
+                        let client = &charhints[&a.hint];
+                        let in_nick = |e: WintError| CommandError::new(e.to_string(), a.nick_start, a.nick_end);
+                        place_client(&tilings, &a.nick, client, &monitors, &decorations).map_err(in_nick)?.ok_or_else(|| {
+                            let monitor = monitor_of(&monitors, client).unwrap_or(&monitors[0]);
+                            CommandError::new(
+                                format!("no nick {} for screen resolution {}", a.nick, monitor.resolution()),
+                                a.nick_start,
+                                a.nick_end,
+                            )
+                        })
+                    }).collect::<Result<Vec<Placement>, CommandError>>()
+                })
+            };
+            match layout {
+                Ok(layout) => {
+                    app.quit();
+                    // the focus goes to the first window of the command
+                    let focus = layout.first().map(|p| p.window);
+                    if let Err(e) = do_resize(backend.as_ref(), &layout, focus, &retry) {
+                        println!("ERROR: {}", e);
+                    }
                 }
+                Err(e) => show_error(&e),
             }
         }));
         vbox.add(&entry);
+        vbox.add(&message);
         entry.grab_focus();
         window.show_all();
     });
-    application.run();
+    let empty: Vec<String> = vec![];
+    application.run_with_args(&empty);
     Ok(())
 }
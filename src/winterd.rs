@@ -0,0 +1,67 @@
+extern crate clap;
+
+use clap::{App, Arg};
+use hyprwinter::daemon::{follow_socket, serve, socket_path, Tracker};
+use hyprwinter::history::History;
+use hyprwinter::{get_conf, tmpfile_path, Config, Hyprland};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let clops = App::new("winterd")
+        .author("Andrei Mikhailov")
+        .about("Follows the focus for winj and wint")
+        .arg(
+            Arg::with_name("socket")
+                .help("where to answer queries (default: next to the tmpfile)")
+                .long("socket")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("events")
+                .help("the event socket to follow (default: the one of the running Hyprland)")
+                .long("events")
+                .takes_value(true),
+        )
+        .get_matches();
+    let conf: Config = get_conf()?;
+    let socket = match clops.value_of("socket") {
+        Some(p) => PathBuf::from(p),
+        None => socket_path(&conf.tmpfile),
+    };
+    let mut tracker = Tracker::new(History::load(&tmpfile_path(&conf.tmpfile), conf.history));
+    let hyprland = Hyprland::from_env();
+    match hyprland {
+        Ok(ref h) => {
+            if let Err(e) = tracker.seed(h) {
+                println!("ERROR: could not read the windows: {}", e);
+            }
+        }
+        Err(ref e) => println!("ERROR: {}", e),
+    }
+    let events = match clops.value_of("events") {
+        Some(p) => PathBuf::from(p),
+        None => hyprland?.events_socket(),
+    };
+
+    if UnixStream::connect(&socket).is_ok() {
+        return Err(format!("winterd is already running at {}", socket.display()).into());
+    }
+    // left behind by a winterd which did not exit cleanly
+    let _ = std::fs::remove_file(&socket);
+    let listener = UnixListener::bind(&socket)?;
+    println!("-- winterd: answering at {}", socket.display());
+
+    let tracker = Arc::new(Mutex::new(tracker));
+    {
+        let tracker = Arc::clone(&tracker);
+        thread::spawn(move || serve(&listener, &tracker));
+    }
+    let result = follow_socket(&events, &tracker);
+    println!("-- winterd: event socket {} closed", events.display());
+    let _ = std::fs::remove_file(&socket);
+    result?;
+    Ok(())
+}